@@ -1,29 +1,88 @@
 use std::{
     fs::{self},
-    path::PathBuf,
-    sync::Arc,
-    time::Duration,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
+use anyhow::{anyhow, Error};
 use flume::{Receiver, RecvTimeoutError, Sender};
+use serde_json::{json, Value};
 
 use crate::{
     actor::{Actor, ActorHandle},
-    config::Config,
-    console::ConsoleActor,
-    driver::{self, Driver},
+    artwork::{Artwork, ArtworkActor, ArtworkFeed},
+    config::{self, Config, ConfigError},
+    console::{ConsoleActor, OutputFormat},
+    control::ControlServer,
+    discord::DiscordActor,
+    driver::{self, Driver, PlayerCommand},
     file::FileWriterActor,
+    history::{History, HistoryActor, HistoryEvent, SessionMarker},
+    hook::HookActor,
+    json::JsonWriterActor,
+    mqtt::MqttActor,
+    obs::ObsActor,
+    plugin::PluginRegistry,
+    scrobble::ScrobbleActor,
+    server::ServerActor,
     song::SongInfo,
-    window::WindowActor,
+    song_override::SongOverride,
+    status::{OutputStatus, Status},
+    twitch::TwitchActor,
+    webhook::WebhookActor,
 };
 
+#[cfg(unix)]
+use crate::dashboard::DashboardActor;
+#[cfg(target_os = "linux")]
+use crate::notification::NotificationActor;
+#[cfg(target_os = "windows")]
+use crate::window::WindowActor;
+
 pub enum LifecycleEvent {
     Exit,
+    /// Asks the driver to control its media player.
+    Player(PlayerCommand),
+    /// Marks the start or the end of a session in the song history.
+    Session(SessionMarker),
+    /// Stops sending song changes to outputs, which keep showing the last song.
+    Pause,
+    /// Sends song changes to outputs again.
+    Resume,
+    /// Shows custom text in outputs instead of the song, or the song again if `None`.
+    Override(Option<SongOverride>),
+    /// Reads the config file again and recreates the driver and outputs with it.
+    /// The result is sent back if a sender is provided.
+    Reload(Option<Sender<Result<(), Error>>>),
+    /// Asks the driver for the song right away.
+    Refresh,
+}
+
+pub const CONFIG_FILE_NAME: &str = "config.json";
+
+/// How often to check whether the config file has changed.
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long to wait for an output to stop when the config is reloaded.
+const OUTPUT_STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An output actor, with a name to show in status reports.
+struct Output {
+    name: String,
+    /// The parts of the config the output was started with.
+    settings: Value,
+    actor: ActorHandle<Option<SongInfo>>,
 }
 
 pub struct App {
     /// Path to the directory where this app holds its data.
     data_directory: PathBuf,
+    /// Path to the config file, in the data directory unless set otherwise.
+    config_path: PathBuf,
+    /// Driver to use instead of the one in the config file.
+    driver_override: Option<String>,
     /// Configuration of the application.
     config: Arc<Config>,
     /// A template for a lifecycle sender.
@@ -32,54 +91,220 @@ pub struct App {
     lifecycle_sender: Sender<LifecycleEvent>,
     lifecycle_receiver: Receiver<LifecycleEvent>,
     /// Actor that manages writing song data to console, if one exists.
-    console_actor: Option<ActorHandle<Option<SongInfo>>>,
+    console_actor: Option<Output>,
     window_actor: Option<ActorHandle<Option<SongInfo>>>,
-    file_actor: Option<ActorHandle<Option<SongInfo>>>,
+    /// Terminal dashboard, if the app runs in an interactive terminal.
+    dashboard_actor: Option<ActorHandle<Option<SongInfo>>>,
+    /// Whether the dashboard has been started; it is taken out of the app once it runs.
+    dashboard_running: bool,
+    /// Actors that write song data to text files, one per configured file.
+    file_actors: Vec<Output>,
+    /// Actor that writes song data as JSON, if enabled.
+    json_actor: Option<Output>,
+    /// Actor that records songs in the history database, if enabled.
+    history_actor: Option<Output>,
+    /// Forwards session markers and pauses to the history actor, if one exists.
+    history_sender: Option<Sender<HistoryEvent>>,
+    /// Actor that serves song data over HTTP, if enabled.
+    server_actor: Option<Output>,
+    /// Actor that writes album art to an image file, if enabled.
+    artwork_actor: Option<Output>,
+    /// Passes the cover art from the artwork actor to the outputs that show it.
+    artwork_feed: ArtworkFeed,
+    /// Actor that scrobbles songs to Last.fm and ListenBrainz, if enabled.
+    scrobble_actor: Option<Output>,
+    /// Actor that shows the song on the user's Discord profile, if enabled.
+    discord_actor: Option<Output>,
+    /// Actor that answers song requests in Twitch chat, if enabled.
+    twitch_actor: Option<Output>,
+    /// Actors that POST song data to webhooks, one per configured URL.
+    webhook_actors: Vec<Output>,
+    /// Actors that run commands on song changes, one per configured hook.
+    hook_actors: Vec<Output>,
+    /// Actor that publishes song data to an MQTT broker, if enabled.
+    mqtt_actor: Option<Output>,
+    /// Actor that updates sources in OBS over obs-websocket, if enabled.
+    obs_actor: Option<Output>,
+    /// Actor that shows desktop notifications, if enabled.
+    notification_actor: Option<Output>,
+    /// Actors forwarding song data to outputs provided by plugins.
+    plugin_actors: Vec<Output>,
+    /// Outputs running before the config was reloaded, to be kept or stopped.
+    previous_outputs: Vec<Output>,
+    /// Plugins loaded from the data directory.
+    plugins: PluginRegistry,
     /// The driver for resolving current song data.
     driver: Box<dyn Driver>,
     /// Time interval between requesting song information.
     polling_interval: Duration,
+    /// What the app is doing, shared with the dashboard.
+    status: Arc<Mutex<Status>>,
+    /// Whether song changes are held back from outputs.
+    paused: bool,
+    /// Text shown by outputs instead of the song, if set.
+    song_override: Option<SongOverride>,
+    /// How songs are written to standard output.
+    output_format: OutputFormat,
+    /// Whether to print the current song and exit.
+    once: bool,
+    /// Whether to refuse to start with an unreadable config file, rather than use the defaults.
+    strict_config: bool,
+}
+
+/// Returns the path to the directory where this app holds its data.
+pub fn data_directory() -> PathBuf {
+    dirs::config_dir().unwrap().join("Frixuu.CurrentSong")
 }
 
 /// A helper object for creating the application.
-pub struct AppBuilder {}
+pub struct AppBuilder {
+    data_directory: Option<PathBuf>,
+    config_path: Option<PathBuf>,
+    driver: Option<String>,
+    output_format: OutputFormat,
+    once: bool,
+    strict_config: bool,
+}
 
 impl AppBuilder {
     /// Creates a new AppBuilder with the default configuration.
     pub fn new() -> Self {
-        Self {}
+        Self {
+            data_directory: None,
+            config_path: None,
+            driver: None,
+            output_format: OutputFormat::Text,
+            once: false,
+            strict_config: false,
+        }
     }
 
-    pub fn build(self) -> App {
-        let data_directory = dirs::config_dir().unwrap().join("Frixuu.CurrentSong");
+    /// Sets the directory to hold the app's data in, instead of the default one.
+    pub fn data_directory(mut self, data_directory: Option<PathBuf>) -> Self {
+        self.data_directory = data_directory;
+        self
+    }
+
+    /// Sets the config file to use, instead of `config.json` in the data directory.
+    pub fn config_path(mut self, config_path: Option<PathBuf>) -> Self {
+        self.config_path = config_path;
+        self
+    }
+
+    /// Sets the driver to use, instead of the one in the config file.
+    pub fn driver(mut self, driver: Option<String>) -> Self {
+        self.driver = driver;
+        self
+    }
+
+    /// Sets how songs are written to standard output.
+    /// Anything other than text also disables the dashboard.
+    pub fn output_format(mut self, output_format: OutputFormat) -> Self {
+        self.output_format = output_format;
+        self
+    }
+
+    /// Makes the app print the current song and exit, without starting any other outputs.
+    pub fn once(mut self, once: bool) -> Self {
+        self.once = once;
+        self
+    }
+
+    /// Makes building the app fail if the config file cannot be read or is invalid,
+    /// instead of running with the default config, which may enable unwanted outputs.
+    pub fn strict_config(mut self, strict_config: bool) -> Self {
+        self.strict_config = strict_config;
+        self
+    }
+
+    pub fn build(self) -> Result<App, Error> {
+        let data_directory = self.data_directory.unwrap_or_else(data_directory);
         fs::create_dir_all(&data_directory).expect("cannot create config directory");
+        let config_path = self
+            .config_path
+            .unwrap_or_else(|| data_directory.join(CONFIG_FILE_NAME));
 
         let (s, r) = flume::unbounded::<LifecycleEvent>();
         let mut app = App {
             data_directory,
+            config_path,
+            driver_override: self.driver,
             config: Arc::new(Config::default()),
             lifecycle_sender: s,
             lifecycle_receiver: r,
             console_actor: None,
             window_actor: None,
-            file_actor: None,
+            dashboard_actor: None,
+            dashboard_running: false,
+            file_actors: Vec::new(),
+            json_actor: None,
+            history_actor: None,
+            history_sender: None,
+            server_actor: None,
+            artwork_actor: None,
+            artwork_feed: ArtworkFeed::default(),
+            scrobble_actor: None,
+            discord_actor: None,
+            twitch_actor: None,
+            webhook_actors: Vec::new(),
+            hook_actors: Vec::new(),
+            mqtt_actor: None,
+            obs_actor: None,
+            notification_actor: None,
+            plugin_actors: Vec::new(),
+            previous_outputs: Vec::new(),
+            plugins: PluginRegistry::default(),
             driver: driver::noop(),
             polling_interval: Duration::from_millis(1500),
+            status: Arc::new(Mutex::new(Status::default())),
+            paused: false,
+            song_override: None,
+            output_format: self.output_format,
+            once: self.once,
+            strict_config: self.strict_config,
         };
 
-        app.load_config();
+        app.load_config()?;
+        app.load_plugins();
         app.load_driver();
+        if app.once {
+            app.add_write_to_stdout();
+            return Ok(app);
+        }
+        app.song_override = SongOverride::load(&app.data_directory);
         app.setup_interrupts();
+        app.setup_control_socket();
+        app.watch_config();
 
-        app.add_write_to_stdout();
         app.add_gui_window();
-        app.add_write_to_file();
+        app.add_dashboard();
+        app.add_outputs();
 
-        app
+        Ok(app)
     }
 }
 
 impl App {
+    /// Creates the actors that receive song changes, except for the interactive ones,
+    /// which are kept when the config is reloaded.
+    fn add_outputs(&mut self) {
+        self.add_write_to_stdout();
+        self.add_write_to_file();
+        self.add_write_json();
+        self.add_write_artwork();
+        self.add_history();
+        self.add_http_server();
+        self.add_scrobbling();
+        self.add_discord_presence();
+        self.add_twitch_bot();
+        self.add_webhooks();
+        self.add_hooks();
+        self.add_mqtt();
+        self.add_obs();
+        self.add_notifications();
+        self.add_plugin_outputs();
+    }
+
     /// Registers SIGINT and SIGTERM listeners for graceful shutdown invocation.
     fn setup_interrupts(&mut self) {
         let sender = self.lifecycle_sender.clone();
@@ -91,40 +316,122 @@ impl App {
         .expect("cannot set handler");
     }
 
-    fn load_config(&mut self) {
-        const CONFIG_FILE_NAME: &'static str = "config.json";
-        let config_path = self.data_directory.join(CONFIG_FILE_NAME);
-        let config = match Config::try_read(&config_path) {
+    /// Lets other programs control the app through a local socket.
+    fn setup_control_socket(&mut self) {
+        if self.config.control().enabled {
+            let data_directory = self.data_directory.clone();
+            let status = self.status.clone();
+            let lifecycle_sender = self.lifecycle_sender.clone();
+            ControlServer::new(data_directory, status, lifecycle_sender).start();
+        }
+    }
+
+    /// Reloads the config whenever the file changes, e.g. when it is saved in an editor.
+    fn watch_config(&mut self) {
+        let config_path = self.config_path.clone();
+        let lifecycle_sender = self.lifecycle_sender.clone();
+        thread::spawn(move || {
+            let version = |path: &Path| {
+                let metadata = fs::metadata(path).ok()?;
+                Some((metadata.modified().ok(), metadata.len()))
+            };
+            let mut last_version = version(&config_path);
+            let mut changed = false;
+            loop {
+                thread::sleep(CONFIG_POLL_INTERVAL);
+                let current_version = version(&config_path);
+                if current_version != last_version {
+                    // Editors may save in several steps, so wait until the file stops changing
+                    last_version = current_version;
+                    changed = true;
+                    continue;
+                }
+                if changed && current_version.is_some() {
+                    changed = false;
+                    if lifecycle_sender.send(LifecycleEvent::Reload(None)).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Applies the options given on the command line to a config read from the file.
+    fn apply_overrides(&self, config: &mut Config) {
+        if let Some(driver) = &self.driver_override {
+            config.set_driver_name(driver.clone());
+        }
+    }
+
+    fn load_config(&mut self) -> Result<(), Error> {
+        let config_path = self.config_path.clone();
+        let mut config = match Config::try_read(&config_path) {
             Ok(cfg) => cfg,
-            Err(_) => {
-                println!("Trying to write a new config file to {:?}", &config_path);
+            // A quick look at the song should not leave files and windows behind
+            Err(ConfigError::Missing) if self.once => Config::default(),
+            Err(ConfigError::Missing) => {
+                info!("Writing a new config file to {:?}", &config_path);
                 let config = Config::default();
+                if let Err(err) = config.try_save(&config_path) {
+                    warn!("  | Cannot save the config file: {err}");
+                } else if let Err(err) = open::that(&self.data_directory) {
+                    warn!("  | Cannot reveal the data directory: {err}");
+                }
                 config
-                    .try_save(&config_path)
-                    .expect("Cannot save config file");
-
-                open::that(&self.data_directory).expect("Cannot reveal data directory");
-                config
+            }
+            Err(err) if self.strict_config && !matches!(err, ConfigError::Missing) => {
+                return Err(anyhow!(
+                    "cannot read the config file {config_path:?}: {err}"
+                ));
+            }
+            Err(err) => {
+                // Never save over the file, it is likely a typo away from what the user wants
+                warn!("Cannot read the config file {:?}: {err}", &config_path);
+                if let ConfigError::Invalid(_) = err {
+                    match config::back_up(&config_path) {
+                        Ok(backup) => info!("  | A copy of the file is kept at {backup:?}"),
+                        Err(err) => warn!("  | Cannot back up the file: {err}"),
+                    }
+                }
+                warn!("  | Using the default config until the file is fixed");
+                Config::default()
             }
         };
 
+        self.apply_overrides(&mut config);
         self.config = Arc::new(config);
+        Ok(())
+    }
+
+    fn load_plugins(&mut self) {
+        self.plugins = PluginRegistry::load(&self.data_directory, self.config.plugins());
     }
 
     fn load_driver(&mut self) {
         let driver_name = self.config.driver_name();
-        self.driver = driver::create(&driver_name).unwrap_or_else(|| {
-            eprintln!("  | Unknown driver name: \"{}\"", &driver_name);
-            driver::noop()
-        });
+        self.driver = driver::create(driver_name, &self.config, &self.data_directory)
+            .or_else(|| self.plugins.create_driver(driver_name))
+            .unwrap_or_else(|| {
+                warn!("  | Unknown driver name: \"{}\"", &driver_name);
+                driver::noop()
+            });
     }
 
     /// Registers a thread in this app which purpose is to write song info to standard output.
     fn add_write_to_stdout(&mut self) {
+        if self.dashboard_running {
+            // The dashboard shows the song already and would be drawn over
+            return;
+        }
+        let output_format = self.output_format;
         let config = self.config.clone();
-        self.console_actor = ConsoleActor::new(config).spawn().into();
+        let settings = json!(config.song_format());
+        self.console_actor = self.start_output("console", settings, || {
+            ConsoleActor::new(output_format, config).spawn().into()
+        });
     }
 
+    #[cfg(target_os = "windows")]
     fn add_gui_window(&mut self) {
         let lifecycle_sender = self.lifecycle_sender.clone();
         self.window_actor = WindowActor::new(lifecycle_sender, self.config.clone())
@@ -132,33 +439,421 @@ impl App {
             .into();
     }
 
+    #[cfg(not(target_os = "windows"))]
+    fn add_gui_window(&mut self) {}
+
+    /// Takes over the terminal with a dashboard, if the app runs in one interactively.
+    #[cfg(unix)]
+    fn add_dashboard(&mut self) {
+        use std::io::{stdin, stdout, IsTerminal};
+        if self.config.dashboard().enabled
+            && self.output_format == OutputFormat::Text
+            && stdin().is_terminal()
+            && stdout().is_terminal()
+        {
+            let data_directory = self.data_directory.clone();
+            let status = self.status.clone();
+            let lifecycle_sender = self.lifecycle_sender.clone();
+            let config = self.config.clone();
+            self.dashboard_actor =
+                DashboardActor::new(data_directory, status, lifecycle_sender, config)
+                    .spawn()
+                    .into();
+            self.dashboard_running = true;
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn add_dashboard(&mut self) {}
+
     fn add_write_to_file(&mut self) {
         let config = self.config.clone();
+        self.file_actors = config
+            .files()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, file)| {
+                let name = format!("file {}", file.path.display());
+                let settings = json!([file, config.song_format()]);
+                let path = self.data_directory.join(&file.path);
+                let config = config.clone();
+                self.start_output(&name, settings, || {
+                    FileWriterActor::new(path, index, config).spawn().into()
+                })
+            })
+            .collect();
+    }
+
+    fn add_write_json(&mut self) {
+        if self.config.json().enabled {
+            let data_directory = self.data_directory.clone();
+            let config = self.config.clone();
+            let settings = json!(config.json());
+            self.json_actor = self.start_output("json", settings, || {
+                JsonWriterActor::new(&data_directory, config).spawn().into()
+            });
+        }
+    }
+
+    fn add_write_artwork(&mut self) {
+        if self.config.artwork().enabled {
+            let data_directory = self.data_directory.clone();
+            let feed = self.artwork_feed.clone();
+            let config = self.config.clone();
+            let settings = json!(config.artwork());
+            self.artwork_actor = self.start_output("artwork", settings, || {
+                ArtworkActor::new(&data_directory, feed, config)
+                    .spawn()
+                    .into()
+            });
+        }
+    }
+
+    /// Subscribes to the cover art, if the artwork output is enabled.
+    fn subscribe_artwork(&self) -> Option<Receiver<Artwork>> {
+        self.config
+            .artwork()
+            .enabled
+            .then(|| self.artwork_feed.subscribe())
+    }
+
+    fn add_history(&mut self) {
+        if !self.config.history().enabled {
+            self.history_sender = None;
+            return;
+        }
+        let data_directory = self.data_directory.clone();
+        let paused = self.paused;
+        let config = self.config.clone();
+        let settings = json!([config.history(), config.driver_name()]);
+        let mut history_sender = None;
+        self.history_actor = self.start_output("history", settings, || {
+            match History::open(&data_directory) {
+                Ok(history) => {
+                    let (sender, receiver) = flume::unbounded();
+                    if paused {
+                        let _ = sender.send(HistoryEvent::Paused(true));
+                    }
+                    history_sender = Some(sender);
+                    HistoryActor::new(history, receiver, config).spawn().into()
+                }
+                Err(err) => {
+                    warn!("  | Cannot open song history: {err}");
+                    None
+                }
+            }
+        });
+        if self.history_actor.is_none() || history_sender.is_some() {
+            // A history actor kept from before the reload still listens to the old sender
+            self.history_sender = history_sender;
+        }
+    }
+
+    fn add_http_server(&mut self) {
+        if self.config.server().enabled {
+            let data_directory = self.data_directory.clone();
+            let lifecycle_sender = self.lifecycle_sender.clone();
+            let config = self.config.clone();
+            let settings = json!([config.server(), config.history().enabled]);
+            self.server_actor = self.start_output("http server", settings, || {
+                ServerActor::new(data_directory, lifecycle_sender, config)
+                    .spawn()
+                    .into()
+            });
+        }
+    }
+
+    fn add_scrobbling(&mut self) {
         let data_directory = self.data_directory.clone();
-        let path = data_directory.join("song.txt");
-        self.file_actor = FileWriterActor::new(path, config).spawn().into();
+        let config = self.config.clone();
+        let settings = json!(config.scrobble());
+        self.scrobble_actor = self.start_output("scrobbling", settings, || {
+            ScrobbleActor::new(&data_directory, config).map(|a| a.spawn())
+        });
+    }
+
+    fn add_discord_presence(&mut self) {
+        if self.config.discord().enabled {
+            let config = self.config.clone();
+            let settings = json!(config.discord());
+            self.discord_actor = self.start_output("discord", settings, || {
+                DiscordActor::new(config).spawn().into()
+            });
+        }
+    }
+
+    fn add_twitch_bot(&mut self) {
+        if self.config.twitch().enabled {
+            let lifecycle_sender = self.lifecycle_sender.clone();
+            let config = self.config.clone();
+            let settings = json!(config.twitch());
+            self.twitch_actor = self.start_output("twitch", settings, || {
+                TwitchActor::new(lifecycle_sender, config).spawn().into()
+            });
+        }
+    }
+
+    fn add_webhooks(&mut self) {
+        let config = self.config.clone();
+        self.webhook_actors = config
+            .webhooks()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, webhook)| {
+                let name = format!("webhook {}", webhook.url);
+                let settings = json!([webhook, config.song_format()]);
+                let config = config.clone();
+                self.start_output(&name, settings, || {
+                    WebhookActor::new(index, config).spawn().into()
+                })
+            })
+            .collect();
+    }
+
+    fn add_hooks(&mut self) {
+        let config = self.config.clone();
+        self.hook_actors = config
+            .hooks()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, hook)| {
+                let name = format!("hook {}", hook.command.join(" "));
+                let settings = json!([hook, config.song_format()]);
+                let config = config.clone();
+                self.start_output(&name, settings, || {
+                    HookActor::new(index, config).spawn().into()
+                })
+            })
+            .collect();
+    }
+
+    fn add_mqtt(&mut self) {
+        if self.config.mqtt().enabled {
+            let config = self.config.clone();
+            let settings = json!(config.mqtt());
+            self.mqtt_actor =
+                self.start_output("mqtt", settings, || MqttActor::new(config).spawn().into());
+        }
+    }
+
+    fn add_obs(&mut self) {
+        if self.config.obs().enabled {
+            let artwork = self.subscribe_artwork();
+            let config = self.config.clone();
+            let settings = json!([config.obs(), config.song_format(), artwork.is_some()]);
+            self.obs_actor = self.start_output("obs", settings, || {
+                ObsActor::new(artwork, config).spawn().into()
+            });
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn add_notifications(&mut self) {
+        if self.config.notifications().enabled {
+            let artwork = self.subscribe_artwork();
+            let config = self.config.clone();
+            let settings = json!([config.notifications(), artwork.is_some()]);
+            self.notification_actor = self.start_output("notifications", settings, || {
+                NotificationActor::new(artwork, config).spawn().into()
+            });
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn add_notifications(&mut self) {}
+
+    fn add_plugin_outputs(&mut self) {
+        let settings = json!(self.config.plugins());
+        self.plugin_actors = self
+            .plugins
+            .outputs()
+            .into_iter()
+            .filter_map(|actor| {
+                let name = format!("plugin {}", actor.name());
+                self.start_output(&name, settings.clone(), || actor.spawn().into())
+            })
+            .collect();
+    }
+
+    /// Starts an output, or keeps the one that ran with the same settings before the config
+    /// was reloaded, so that it does not clear what it shows or start over.
+    fn start_output(
+        &mut self,
+        name: &str,
+        settings: Value,
+        start: impl FnOnce() -> Option<ActorHandle<Option<SongInfo>>>,
+    ) -> Option<Output> {
+        if let Some(index) = self.previous_outputs.iter().position(|o| o.name == name) {
+            let previous = self.previous_outputs.remove(index);
+            if previous.settings == settings {
+                return Some(previous);
+            }
+            // Let the new output take over the resources of the old one, like a port
+            stop_output(previous);
+        }
+        start().map(|actor| Output {
+            name: name.to_string(),
+            settings,
+            actor,
+        })
+    }
+
+    /// Takes the output actors out of the app.
+    fn take_outputs(&mut self) -> Vec<Output> {
+        let mut outputs = [
+            self.console_actor.take(),
+            self.json_actor.take(),
+            self.artwork_actor.take(),
+            self.history_actor.take(),
+            self.server_actor.take(),
+            self.scrobble_actor.take(),
+            self.discord_actor.take(),
+            self.twitch_actor.take(),
+            self.mqtt_actor.take(),
+            self.obs_actor.take(),
+            self.notification_actor.take(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        outputs.append(&mut self.file_actors);
+        outputs.append(&mut self.webhook_actors);
+        outputs.append(&mut self.hook_actors);
+        outputs.append(&mut self.plugin_actors);
+        outputs
+    }
+
+    /// Reads the config file again and applies it to the driver and outputs,
+    /// restarting only the ones whose settings have changed.
+    /// New outputs are sent the song, while the kept ones still show it.
+    /// The current config is kept if the file cannot be read.
+    fn reload(&mut self, outputs: &mut Vec<Output>, song: &Option<SongInfo>) -> Result<(), Error> {
+        let mut config = Config::try_read(&self.config_path)?;
+        self.apply_overrides(&mut config);
+        // Keep the driver if its settings are the same, so that it does not lose track of the song
+        let driver_changed = !config.driver_settings_eq(&self.config);
+        if driver_changed {
+            // The old driver may still listen on a port the new one needs
+            self.driver = driver::noop();
+        }
+        let plugins_changed = config.plugins() != self.config.plugins();
+        self.config = Arc::new(config);
+        if plugins_changed {
+            self.load_plugins();
+        }
+        if driver_changed {
+            self.load_driver();
+        }
+
+        self.previous_outputs = std::mem::take(outputs);
+        let previous = self
+            .previous_outputs
+            .iter()
+            .map(|output| output.actor.thread_handle.thread().id())
+            .collect::<Vec<_>>();
+        self.add_outputs();
+        // Whatever is left has been turned off in the config
+        for output in std::mem::take(&mut self.previous_outputs) {
+            stop_output(output);
+        }
+        *outputs = self.take_outputs();
+
+        if song.is_some() {
+            // New outputs start out empty
+            let started = outputs
+                .iter()
+                .filter(|output| !previous.contains(&output.actor.thread_handle.thread().id()));
+            for output in started {
+                let _ = output.actor.send(song.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the state of the app into the status shared with the dashboard.
+    fn update_status(&self, song: &Option<SongInfo>, outputs: &[Output]) {
+        let mut status = self.status.lock().unwrap();
+        let now = SystemTime::now();
+        if status.song != *song {
+            status.song = song.clone();
+            status.song_since = song.as_ref().map(|_| now);
+        }
+        status.last_fetch = Some(now);
+        status.driver = self.config.driver_name().to_string();
+        status.paused = self.paused;
+        status.song_override = self.song_override.clone();
+        status.outputs = outputs
+            .iter()
+            .map(|output| OutputStatus {
+                name: output.name.clone(),
+                running: !output.actor.thread_handle.is_finished(),
+            })
+            .collect();
+    }
+
+    /// The song to send to outputs: the custom text, if set, or the driver's song.
+    fn shown_song(&self, song: &Option<SongInfo>) -> Option<SongInfo> {
+        match &self.song_override {
+            Some(song_override) => Some(song_override.song()),
+            None => song.clone(),
+        }
+    }
+
+    /// Stops or starts sending song changes to outputs.
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        if let Some(sender) = &self.history_sender {
+            let _ = sender.send(HistoryEvent::Paused(paused));
+        }
+    }
+
+    /// Sets or clears the custom text, remembering it for the next start.
+    fn set_override(&mut self, song_override: Option<SongOverride>) {
+        self.song_override = song_override.filter(|o| !o.text.trim().is_empty());
+        SongOverride::save(self.song_override.as_ref(), &self.data_directory);
+    }
+
+    /// Prints the current song once, waiting until it has been written.
+    fn run_once(mut self) {
+        let song = self.driver.fetch_song_info();
+        let shown = self.shown_song(&song);
+        if let Some(Output { actor: console, .. }) = self.console_actor.take() {
+            let _ = console.send(shown);
+            drop(console.sender);
+            let _ = console.thread_handle.join();
+        }
     }
 
     /// Runs the application.
     /// This method exits only if the app has been gracefully shut down.
     pub fn run(mut self) {
-        let actors = [self.console_actor, self.window_actor, self.file_actor]
+        if self.once {
+            return self.run_once();
+        }
+        let interactive = [self.window_actor.take(), self.dashboard_actor.take()]
             .into_iter()
-            .filter_map(|o| o)
+            .flatten()
             .collect::<Vec<_>>();
+        let mut outputs = self.take_outputs();
 
-        let mut last_song: Option<SongInfo> = None;
+        let mut last_sent: Option<SongInfo> = None;
 
         let lifecycle_receiver = self.lifecycle_receiver.clone();
 
         loop {
+            if self.song_override.as_ref().is_some_and(|o| o.is_expired()) {
+                self.set_override(None);
+            }
             let song = self.driver.fetch_song_info();
+            self.update_status(&song, &outputs);
 
             // Only raise when song has changed
-            if song != last_song {
-                last_song = song.clone();
-                for actor in &actors {
-                    actor.send(song.clone()).expect("Cannot send updated song");
+            let shown = self.shown_song(&song);
+            if !self.paused && shown != last_sent {
+                last_sent = shown.clone();
+                for actor in interactive.iter().chain(outputs.iter().map(|o| &o.actor)) {
+                    // An output that stopped is reported in the status instead
+                    let _ = actor.send(shown.clone());
                 }
             }
 
@@ -169,11 +864,63 @@ impl App {
                 Err(RecvTimeoutError::Disconnected) | Ok(LifecycleEvent::Exit) => {
                     break;
                 }
+                Ok(LifecycleEvent::Player(command)) => {
+                    // The song will be fetched again right away to reflect the change
+                    if let Err(err) = self.driver.control(&command) {
+                        warn!("  | Cannot execute {:?}: {}", command, err);
+                    }
+                }
+                Ok(LifecycleEvent::Session(marker)) => match &self.history_sender {
+                    Some(sender) => {
+                        let _ = sender.send(HistoryEvent::Session(marker));
+                    }
+                    None => warn!("  | Cannot mark session: song history is disabled"),
+                },
+                Ok(LifecycleEvent::Pause) => self.set_paused(true),
+                Ok(LifecycleEvent::Resume) => self.set_paused(false),
+                Ok(LifecycleEvent::Override(song_override)) => self.set_override(song_override),
+                Ok(LifecycleEvent::Reload(reply)) => {
+                    let result = self.reload(&mut outputs, &last_sent);
+                    match &result {
+                        Ok(()) => info!("  | Reloaded config"),
+                        Err(err) => warn!("  | Cannot reload config, keeping the old one: {err}"),
+                    }
+                    if let Some(reply) = reply {
+                        let _ = reply.send(result);
+                    }
+                }
+                Ok(LifecycleEvent::Refresh) => {
+                    // The song is fetched at the start of every iteration
+                }
             }
         }
 
-        for actor in actors {
+        for output in outputs {
+            drop(output.actor.sender);
+        }
+        for actor in interactive {
             drop(actor.sender);
+            // The dashboard has to give the terminal back before the app exits,
+            // while the window runs its own event loop until it is closed
+            if !cfg!(target_os = "windows") {
+                let _ = actor.thread_handle.join();
+            }
         }
     }
 }
+
+/// Stops an output, waiting a while for it to finish, e.g. to clear what it shows.
+/// An output stuck on the network is left to finish in the background.
+fn stop_output(output: Output) {
+    let Output { name, actor, .. } = output;
+    drop(actor.sender);
+    let deadline = Instant::now() + OUTPUT_STOP_TIMEOUT;
+    while !actor.thread_handle.is_finished() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(50));
+    }
+    if actor.thread_handle.is_finished() {
+        let _ = actor.thread_handle.join();
+    } else {
+        warn!("  | Output {name} did not stop in time, starting the new one anyway");
+    }
+}
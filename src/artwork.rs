@@ -0,0 +1,441 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{anyhow, Error};
+use flume::{Receiver, Sender};
+use lofty::{file::TaggedFileExt, picture::PictureType};
+use sha2::{Digest, Sha256};
+use ureq::Agent;
+use url::Url;
+
+use crate::{
+    config::{ArtworkConfig, ArtworkLookup, Config},
+    file::write_atomic,
+    http,
+    song::SongInfo,
+    Actor, ActorHandle,
+};
+
+/// A 1x1 transparent image, written when no placeholder is configured.
+const TRANSPARENT_PIXEL: &[u8] = include_bytes!("../placeholder.png");
+
+/// How long to wait before looking for art that could not be found again.
+const MISS_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Extensions of the image formats the art is recognized as.
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "gif", "webp", "bmp"];
+
+const USER_AGENT: &str = concat!(
+    "CurrentSong/",
+    env!("CARGO_PKG_VERSION"),
+    " ( https://github.com/Frixuu/CurrentSong )"
+);
+
+/// Album art written to the image file.
+#[derive(Clone)]
+pub struct Artwork {
+    /// The song the art was looked up for, `None` when nothing is playing.
+    pub song: Option<SongInfo>,
+    pub path: PathBuf,
+    /// Whether no art was found and the placeholder was written instead.
+    /// Only the notifications, which are Linux only, tell the two apart.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub placeholder: bool,
+}
+
+/// Hands the album art written by the artwork output to the outputs that show it.
+/// Outputs subscribing late, e.g. after the config was reloaded, get the last art right away.
+#[derive(Clone, Default)]
+pub struct ArtworkFeed {
+    state: Arc<Mutex<FeedState>>,
+}
+
+#[derive(Default)]
+struct FeedState {
+    last: Option<Artwork>,
+    subscribers: Vec<Sender<Artwork>>,
+}
+
+impl ArtworkFeed {
+    pub fn subscribe(&self) -> Receiver<Artwork> {
+        let (sender, receiver) = flume::unbounded();
+        let mut state = self.state.lock().unwrap();
+        if let Some(last) = &state.last {
+            let _ = sender.send(last.clone());
+        }
+        state.subscribers.push(sender);
+        receiver
+    }
+
+    fn publish(&self, artwork: Artwork) {
+        let mut state = self.state.lock().unwrap();
+        // Outputs that have shut down are forgotten
+        state
+            .subscribers
+            .retain(|subscriber| subscriber.send(artwork.clone()).is_ok());
+        state.last = Some(artwork);
+    }
+}
+
+/// An actor that writes the current song's album art to an image file,
+/// so that it can be shown with an OBS image source.
+pub struct ArtworkActor {
+    config: Arc<Config>,
+    /// Where to write the art, the extension is set from the image format
+    /// unless the file name is kept.
+    path: PathBuf,
+    cache_directory: PathBuf,
+    feed: ArtworkFeed,
+}
+
+impl ArtworkActor {
+    pub fn new(data_directory: &Path, feed: ArtworkFeed, config: Arc<Config>) -> Self {
+        Self {
+            path: data_directory.join(&config.artwork().file_name),
+            cache_directory: data_directory.join("cache").join("artwork"),
+            feed,
+            config,
+        }
+    }
+}
+
+impl Actor for ArtworkActor {
+    type MessageType = Option<SongInfo>;
+    fn spawn(self) -> ActorHandle<Self::MessageType> {
+        let (sender, receiver) = flume::unbounded::<Option<SongInfo>>();
+        ActorHandle {
+            sender,
+            thread_handle: std::thread::spawn(move || {
+                let config = self.config.artwork();
+                let placeholder = match &config.placeholder {
+                    Some(path) => fs::read(path).unwrap_or_else(|err| {
+                        warn!("  | Cannot read placeholder image {path:?}: {err:?}");
+                        TRANSPARENT_PIXEL.to_vec()
+                    }),
+                    None => TRANSPARENT_PIXEL.to_vec(),
+                };
+
+                let mut fetcher = ArtworkFetcher::new(config, self.cache_directory);
+                let mut written: Option<PathBuf> = None;
+                let mut write = |song: Option<SongInfo>, image: Option<&[u8]>| {
+                    let found = image.is_some();
+                    let image = image.unwrap_or(&placeholder);
+                    let path = match image_extension(image) {
+                        Some(extension) if !config.keep_file_name => {
+                            self.path.with_extension(extension)
+                        }
+                        _ => self.path.clone(),
+                    };
+                    if written.as_ref() != Some(&path) && !config.keep_file_name {
+                        // Art in another format would be left behind, looking current
+                        remove_other_formats(&self.path, &path);
+                        written = Some(path.clone());
+                    }
+                    if let Err(err) = write_atomic(&path, image) {
+                        warn!("  | Cannot save {path:?}: {err:?}");
+                    }
+                    self.feed.publish(Artwork {
+                        song,
+                        path,
+                        placeholder: !found,
+                    });
+                };
+
+                write(None, None);
+                while let Ok(song) = receiver.recv() {
+                    // Downloads may be slow, so skip songs that were already replaced
+                    let song = receiver.drain().last().unwrap_or(song);
+                    let image = song.as_ref().and_then(|song| fetcher.find(song));
+                    write(song, image.as_deref());
+                }
+                write(None, None);
+            }),
+        }
+    }
+}
+
+/// Resolves album art from the song's file, the driver or an online lookup.
+struct ArtworkFetcher {
+    agent: Agent,
+    lookup: ArtworkLookup,
+    max_size: u64,
+    cache_directory: PathBuf,
+    /// Largest total size of the cached images, in bytes.
+    cache_size: u64,
+    /// Keys that failed to resolve and when, so they are not retried on every change.
+    misses: HashMap<String, Instant>,
+}
+
+impl ArtworkFetcher {
+    fn new(config: &ArtworkConfig, cache_directory: PathBuf) -> Self {
+        Self {
+            agent: http::agent(Duration::from_secs(config.timeout_secs)),
+            lookup: config.lookup.clone(),
+            max_size: config.max_size_kb * 1024,
+            cache_directory,
+            cache_size: config.cache_size_mb.saturating_mul(1024 * 1024),
+            misses: HashMap::new(),
+        }
+    }
+
+    fn find(&mut self, song: &SongInfo) -> Option<Vec<u8>> {
+        if let Some(image) = song.path.as_deref().and_then(embedded_artwork) {
+            return Some(image);
+        }
+
+        if let Some(url) = &song.artwork_url {
+            let image = if url.starts_with("file:") {
+                // Local players point at art on disk, which needs no caching
+                self.read_file(url)
+                    .map_err(|err| warn!("  | Cannot read album art: {err}"))
+                    .ok()
+            } else {
+                self.cached(url, |f| f.download(url))
+            };
+            if image.is_some() {
+                return image;
+            }
+        }
+
+        match self.lookup {
+            ArtworkLookup::None => None,
+            ArtworkLookup::CoverArtArchive => {
+                let key = format!("caa:{}\n{}", song.artist, song.title);
+                self.cached(&key, |f| f.cover_art_archive(song))
+            }
+        }
+    }
+
+    /// Returns the image stored in the cache under a key, fetching it if not present.
+    fn cached<F>(&mut self, key: &str, fetch: F) -> Option<Vec<u8>>
+    where
+        F: FnOnce(&Self) -> Result<Vec<u8>, Error>,
+    {
+        if let Some(missed_at) = self.misses.get(key) {
+            if missed_at.elapsed() < MISS_TTL {
+                return None;
+            }
+            self.misses.remove(key);
+        }
+
+        let path = self
+            .cache_directory
+            .join(format!("{:x}", Sha256::digest(key)));
+        if let Ok(image) = fs::read(&path) {
+            // The cache is pruned by modification time, so mark the image as recently used
+            let _ = File::options()
+                .write(true)
+                .open(&path)
+                .and_then(|file| file.set_modified(SystemTime::now()));
+            return Some(image);
+        }
+
+        match fetch(self) {
+            Ok(image) => {
+                let _ = fs::create_dir_all(&self.cache_directory);
+                if let Err(err) = fs::write(&path, &image) {
+                    warn!("  | Cannot cache album art: {err:?}");
+                }
+                self.prune();
+                Some(image)
+            }
+            Err(err) => {
+                warn!("  | Cannot fetch album art: {err}");
+                self.misses.insert(key.to_owned(), Instant::now());
+                None
+            }
+        }
+    }
+
+    /// Removes the least recently used images until the cache fits in its size limit.
+    fn prune(&self) {
+        let Ok(entries) = fs::read_dir(&self.cache_directory) else {
+            return;
+        };
+        let mut files = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let metadata = entry.metadata().ok()?;
+                let modified = metadata.modified().ok()?;
+                metadata
+                    .is_file()
+                    .then(|| (modified, metadata.len(), entry.path()))
+            })
+            .collect::<Vec<_>>();
+        let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+        files.sort();
+        for (_, len, path) in files {
+            if total <= self.cache_size {
+                break;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => total -= len,
+                Err(err) => warn!("  | Cannot remove cached album art {path:?}: {err:?}"),
+            }
+        }
+    }
+
+    fn download(&self, url: &str) -> Result<Vec<u8>, Error> {
+        let response = self.agent.get(url).set("User-Agent", USER_AGENT).call()?;
+        self.read_image(response.into_reader(), url)
+    }
+
+    /// Reads a `file://` URL, which players of local files report for art stored on disk.
+    fn read_file(&self, url: &str) -> Result<Vec<u8>, Error> {
+        let path = Url::parse(url)
+            .ok()
+            .and_then(|url| url.to_file_path().ok())
+            .ok_or_else(|| anyhow!("invalid file URL: {url}"))?;
+        let file = File::open(&path).map_err(|err| anyhow!("{path:?}: {err}"))?;
+        self.read_image(file, url)
+    }
+
+    /// Reads an image, failing if it exceeds the size limit.
+    fn read_image(&self, reader: impl Read, source: &str) -> Result<Vec<u8>, Error> {
+        let mut image = Vec::new();
+        reader.take(self.max_size + 1).read_to_end(&mut image)?;
+        if image.len() as u64 > self.max_size {
+            return Err(anyhow!("image at {source} exceeds {} bytes", self.max_size));
+        }
+        Ok(image)
+    }
+
+    /// Looks up the song's release on MusicBrainz and downloads its front cover.
+    fn cover_art_archive(&self, song: &SongInfo) -> Result<Vec<u8>, Error> {
+        let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+        let query = format!(
+            "artist:\"{}\" AND recording:\"{}\"",
+            escape(&song.artist),
+            escape(&song.title)
+        );
+        let response = self
+            .agent
+            .get("https://musicbrainz.org/ws/2/recording/")
+            .set("User-Agent", USER_AGENT)
+            .query("query", &query)
+            .query("fmt", "json")
+            .query("limit", "1")
+            .call()?;
+        let json: serde_json::Value = serde_json::from_reader(response.into_reader())?;
+        let release = json["recordings"][0]["releases"][0]["id"]
+            .as_str()
+            .ok_or_else(|| anyhow!("no release found for {} - {}", song.artist, song.title))?;
+        self.download(&format!(
+            "https://coverartarchive.org/release/{release}/front-250"
+        ))
+    }
+}
+
+/// Returns the file extension of an image's format, recognized by its first bytes.
+fn image_extension(image: &[u8]) -> Option<&'static str> {
+    if image.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("png")
+    } else if image.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("jpg")
+    } else if image.starts_with(b"GIF87a") || image.starts_with(b"GIF89a") {
+        Some("gif")
+    } else if image.starts_with(b"RIFF") && image.get(8..12) == Some(b"WEBP") {
+        Some("webp")
+    } else if image.starts_with(b"BM") {
+        Some("bmp")
+    } else {
+        None
+    }
+}
+
+/// Removes art written in other formats than the one at `current`.
+fn remove_other_formats(path: &Path, current: &Path) {
+    for extension in IMAGE_EXTENSIONS {
+        let other = path.with_extension(extension);
+        if other != current && other.exists() {
+            if let Err(err) = fs::remove_file(&other) {
+                warn!("  | Cannot remove {other:?}: {err:?}");
+            }
+        }
+    }
+}
+
+/// Reads the front cover, or any picture, embedded in the tags of a local file.
+fn embedded_artwork(path: &Path) -> Option<Vec<u8>> {
+    let file = lofty::read_from_path(path).ok()?;
+    let pictures = file
+        .tags()
+        .iter()
+        .flat_map(|tag| tag.pictures())
+        .collect::<Vec<_>>();
+    pictures
+        .iter()
+        .find(|p| p.pic_type() == PictureType::CoverFront)
+        .or_else(|| pictures.first())
+        .map(|p| p.data().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates an empty directory for a test to keep its files in.
+    fn test_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("currentsong-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn song_with_art(url: String) -> SongInfo {
+        SongInfo {
+            artist: "A".to_string(),
+            title: "B".to_string(),
+            artwork_url: Some(url),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reads_art_from_file_urls() {
+        let directory = test_directory("file-art");
+        let path = directory.join("cover art.png");
+        fs::write(&path, TRANSPARENT_PIXEL).unwrap();
+        let url = Url::from_file_path(&path).unwrap().to_string();
+
+        let config = ArtworkConfig::default();
+        let mut fetcher = ArtworkFetcher::new(&config, directory.join("cache"));
+        let image = fetcher.find(&song_with_art(url));
+        assert_eq!(image.as_deref(), Some(TRANSPARENT_PIXEL));
+        // Local art is read every time rather than cached
+        assert!(!directory.join("cache").exists());
+    }
+
+    #[test]
+    fn limits_the_size_of_art_from_file_urls() {
+        let directory = test_directory("file-art-size");
+        let path = directory.join("cover.png");
+        fs::write(&path, vec![0; 2048]).unwrap();
+        let url = Url::from_file_path(&path).unwrap().to_string();
+
+        let config = ArtworkConfig {
+            max_size_kb: 1,
+            ..Default::default()
+        };
+        let mut fetcher = ArtworkFetcher::new(&config, directory.join("cache"));
+        assert!(fetcher.find(&song_with_art(url)).is_none());
+        assert!(fetcher
+            .find(&song_with_art("file:///no/such/cover.png".to_string()))
+            .is_none());
+    }
+
+    #[test]
+    fn recognizes_image_formats() {
+        assert_eq!(image_extension(TRANSPARENT_PIXEL), Some("png"));
+        assert_eq!(image_extension(&[0xff, 0xd8, 0xff, 0xe0]), Some("jpg"));
+        assert_eq!(image_extension(b"RIFF\0\0\0\0WEBPVP8 "), Some("webp"));
+        assert_eq!(image_extension(b"<svg"), None);
+    }
+}
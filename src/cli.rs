@@ -0,0 +1,172 @@
+use std::{env, fs, path::Path, path::PathBuf, process};
+
+use anyhow::{anyhow, Error};
+
+use crate::{
+    config::Config,
+    control, driver,
+    history::History,
+    plugin::PluginRegistry,
+    tracklist::{self, Format, Selection},
+};
+
+/// Lets a subcommand print to the terminal it was started from.
+/// Fails silently when started from Explorer, where there is nothing to print to.
+#[cfg(target_os = "windows")]
+pub fn attach_console() {
+    use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+
+    unsafe { AttachConsole(ATTACH_PARENT_PROCESS) };
+}
+
+/// Reads the config file, naming it in the error.
+fn read_config(config_path: &Path) -> Result<Config, Error> {
+    Config::try_read(config_path).map_err(|err| anyhow!("{}: {err}", config_path.display()))
+}
+
+/// Prints the config as the app sees it, including defaults of settings missing from the file.
+pub fn config_show(config_path: &Path, driver: Option<String>) -> Result<(), Error> {
+    let mut config = read_config(config_path)?;
+    if let Some(driver) = driver {
+        config.set_driver_name(driver);
+    }
+    println!("{}", serde_json::to_string_pretty(&config)?);
+    Ok(())
+}
+
+pub fn config_validate(config_path: &Path) -> Result<(), Error> {
+    read_config(config_path)?;
+    println!("{} is valid", config_path.display());
+    Ok(())
+}
+
+/// Opens the config file in `$VISUAL` or `$EDITOR` and validates it once the editor exits.
+/// Without an editor set, the file is opened with the program associated with it.
+pub fn config_edit(config_path: &Path) -> Result<(), Error> {
+    if !config_path.exists() {
+        Config::default().try_save(config_path)?;
+    }
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty());
+    let Some(editor) = editor else {
+        open::that(config_path)?;
+        return Ok(());
+    };
+
+    // Editors are often set with arguments, such as `code --wait`
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or_default();
+    let status = process::Command::new(program)
+        .args(parts)
+        .arg(config_path)
+        .status()?;
+    if !status.success() {
+        return Err(anyhow!("`{editor}` exited with {status}"));
+    }
+    config_validate(config_path)
+}
+
+/// Prints the drivers that can be used, marking the one in use.
+/// They are listed in the order they are looked up in when names collide.
+pub fn drivers_list(
+    data_directory: &Path,
+    config_path: &Path,
+    driver: Option<String>,
+) -> Result<(), Error> {
+    // Plugins are loaded from the config, but the built-in drivers are worth listing without one
+    let config = read_config(config_path).unwrap_or_default();
+    let current = driver.unwrap_or_else(|| config.driver_name().to_string());
+    let plugins = PluginRegistry::load(data_directory, config.plugins());
+
+    let built_in = driver::built_in_names()
+        .into_iter()
+        .map(|name| (name.to_string(), "built-in".to_string()));
+    let scripts = driver::script_names(data_directory)
+        .into_iter()
+        .map(|name| (name.clone(), format!("script drivers/{name}.rhai")));
+    let plugin_drivers = plugins
+        .driver_names()
+        .into_iter()
+        .map(|(name, plugin)| (name, format!("plugin {plugin}")));
+    for (name, source) in built_in.chain(scripts).chain(plugin_drivers) {
+        let marker = if name == current { '*' } else { ' ' };
+        println!("{marker} {name:<24} {source}");
+    }
+    Ok(())
+}
+
+/// Prints songs from the song history, oldest first.
+pub fn history(
+    data_directory: &Path,
+    count: u32,
+    session: Option<i64>,
+    json: bool,
+) -> Result<(), Error> {
+    let history = History::open(data_directory)?;
+    let plays = match session {
+        Some(session) => history.session(session)?,
+        None => {
+            let mut plays = history.last(count)?;
+            plays.reverse();
+            plays
+        }
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&plays)?);
+        return Ok(());
+    }
+    for play in &plays {
+        let started_at = history.format_time(play.started_at)?;
+        println!("{started_at}  {} - {}", play.artist, play.title);
+    }
+    Ok(())
+}
+
+/// Sends a command to a running instance through the control socket and prints the reply.
+pub fn ctl(data_directory: &Path, command: &[String]) -> Result<(), Error> {
+    let reply = control::request(data_directory, &command.join(" "))?;
+    if reply["ok"] != true {
+        let error = reply["error"].as_str().unwrap_or("unknown error");
+        return Err(anyhow!("{error}"));
+    }
+    println!("{reply}");
+    Ok(())
+}
+
+pub fn export(
+    data_directory: &Path,
+    format: Format,
+    session: Option<i64>,
+    from: Option<String>,
+    to: Option<String>,
+    start: Option<String>,
+    output: Option<PathBuf>,
+) -> Result<(), Error> {
+    let history = History::open(data_directory)?;
+    let parse_time = |time: Option<String>| -> Result<Option<i64>, Error> {
+        let Some(time) = time else {
+            return Ok(None);
+        };
+        match history.parse_time(&time)? {
+            Some(time) => Ok(Some(time)),
+            None => Err(anyhow!("invalid time: {time}")),
+        }
+    };
+
+    let selection = Selection {
+        session,
+        from: parse_time(from)?,
+        to: parse_time(to)?,
+        stream_start: parse_time(start)?,
+    };
+    let (plays, stream_start) = tracklist::select(&history, &selection)?;
+    let tracklist = tracklist::export(&plays, format, stream_start);
+    match output {
+        Some(path) => fs::write(path, tracklist)?,
+        None => print!("{tracklist}"),
+    }
+    Ok(())
+}
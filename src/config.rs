@@ -1,11 +1,43 @@
 use anyhow::Error;
-use serde::{Deserialize, Serialize};
-use std::{fs, io::ErrorKind, path::Path};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+use url::Url;
 
 #[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
 pub struct Config {
     driver: String,
     song_format: String,
+    browser_bridge: BrowserBridgeConfig,
+    mpd: MpdConfig,
+    mpris: MprisConfig,
+    spotify_web: SpotifyWebConfig,
+    /// Names of plugin libraries to load from the `plugins` directory.
+    plugins: Vec<String>,
+    artwork: ArtworkConfig,
+    /// Text files to write the song to.
+    /// Older versions wrote a single file, configured as `file`.
+    #[serde(alias = "file", deserialize_with = "one_or_many")]
+    files: Vec<FileConfig>,
+    json: JsonConfig,
+    history: HistoryConfig,
+    server: ServerConfig,
+    scrobble: ScrobbleConfig,
+    discord: DiscordConfig,
+    twitch: TwitchConfig,
+    /// URLs to POST the song to on every change.
+    webhooks: Vec<WebhookConfig>,
+    mqtt: MqttConfig,
+    obs: ObsConfig,
+    hooks: Vec<HookConfig>,
+    notifications: NotificationConfig,
+    dashboard: DashboardConfig,
+    control: ControlConfig,
 }
 
 impl Default for Config {
@@ -13,6 +45,708 @@ impl Default for Config {
         Config {
             driver: "spotify-desktop".into(),
             song_format: "♫ {artist} - {title}".into(),
+            browser_bridge: BrowserBridgeConfig::default(),
+            mpd: MpdConfig::default(),
+            mpris: MprisConfig::default(),
+            spotify_web: SpotifyWebConfig::default(),
+            plugins: Vec::new(),
+            artwork: ArtworkConfig::default(),
+            files: default_files(),
+            json: JsonConfig::default(),
+            history: HistoryConfig::default(),
+            server: ServerConfig::default(),
+            scrobble: ScrobbleConfig::default(),
+            discord: DiscordConfig::default(),
+            twitch: TwitchConfig::default(),
+            webhooks: Vec::new(),
+            mqtt: MqttConfig::default(),
+            obs: ObsConfig::default(),
+            hooks: Vec::new(),
+            notifications: NotificationConfig::default(),
+            dashboard: DashboardConfig::default(),
+            control: ControlConfig::default(),
+        }
+    }
+}
+
+/// Settings of the "browser-bridge" driver.
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct BrowserBridgeConfig {
+    /// Local port to listen on for reports from the browser.
+    pub port: u16,
+    /// Shared secret the browser must send in the `X-CurrentSong-Token` header.
+    /// Requests are rejected while this is empty.
+    pub token: String,
+    /// Seconds after which the last report is considered stale.
+    pub timeout_secs: u64,
+}
+
+impl Default for BrowserBridgeConfig {
+    fn default() -> BrowserBridgeConfig {
+        BrowserBridgeConfig {
+            port: 48457,
+            token: String::new(),
+            timeout_secs: 30,
+        }
+    }
+}
+
+/// Settings of the "mpd" driver.
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct MpdConfig {
+    pub host: String,
+    pub port: u16,
+    /// Sent after connecting, if the server requires one.
+    pub password: Option<String>,
+    /// The `music_directory` of the server, if it is on this machine.
+    /// Lets the artwork output read covers embedded in the song files.
+    pub music_directory: Option<PathBuf>,
+    /// Timeout of connecting and of a single command, in seconds.
+    pub timeout_secs: u64,
+}
+
+impl Default for MpdConfig {
+    fn default() -> MpdConfig {
+        MpdConfig {
+            host: "127.0.0.1".into(),
+            port: 6600,
+            password: None,
+            music_directory: None,
+            timeout_secs: 2,
+        }
+    }
+}
+
+/// Settings of the "mpris" driver.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct MprisConfig {
+    /// Part of the bus name of the player to follow, such as `spotify` or `firefox`.
+    /// If not set, whichever player is playing is followed.
+    pub player: Option<String>,
+}
+
+/// Settings of the "spotify-web" driver.
+/// The refresh token is obtained once through the authorization code flow,
+/// with the `user-read-currently-playing` and `user-modify-playback-state` scopes.
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct SpotifyWebConfig {
+    /// Client ID and secret of an app, see <https://developer.spotify.com/dashboard>.
+    pub client_id: String,
+    pub client_secret: String,
+    pub refresh_token: String,
+    /// Timeout of a single HTTP request, in seconds.
+    pub timeout_secs: u64,
+}
+
+impl Default for SpotifyWebConfig {
+    fn default() -> SpotifyWebConfig {
+        SpotifyWebConfig {
+            client_id: String::new(),
+            client_secret: String::new(),
+            refresh_token: String::new(),
+            timeout_secs: 5,
+        }
+    }
+}
+
+fn default_files() -> Vec<FileConfig> {
+    vec![FileConfig::default()]
+}
+
+/// Reads a list that may also be given as a single value.
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+/// Settings of a single text file output.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct FileConfig {
+    /// Path of the file, relative to the data directory unless absolute.
+    pub path: PathBuf,
+    /// Format of the song in this file. Uses `song_format` if not set.
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Text written when nothing is playing.
+    #[serde(default)]
+    pub empty_text: String,
+    #[serde(default)]
+    pub encoding: TextEncoding,
+    #[serde(default)]
+    pub line_ending: LineEnding,
+    /// The song is padded with spaces to at least this many characters,
+    /// e.g. to keep a text source from resizing.
+    #[serde(default)]
+    pub min_length: usize,
+    /// Text appended after the song, e.g. a separator for a scrolling text source.
+    #[serde(default)]
+    pub trailing_text: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum TextEncoding {
+    #[default]
+    Utf8,
+    Utf8Bom,
+    /// UTF-16 little endian with a byte order mark, for older tools.
+    Utf16Le,
+}
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl Default for FileConfig {
+    fn default() -> FileConfig {
+        FileConfig {
+            path: "song.txt".into(),
+            template: None,
+            empty_text: String::new(),
+            encoding: TextEncoding::Utf8,
+            line_ending: LineEnding::Lf,
+            min_length: 0,
+            trailing_text: String::new(),
+        }
+    }
+}
+
+/// Settings of the JSON output.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct JsonConfig {
+    pub enabled: bool,
+    /// Path of the JSON document, relative to the data directory unless absolute.
+    pub path: PathBuf,
+    /// If set, every change is also appended as a line to this file.
+    pub log_path: Option<PathBuf>,
+}
+
+impl Default for JsonConfig {
+    fn default() -> JsonConfig {
+        JsonConfig {
+            enabled: true,
+            path: "nowplaying.json".into(),
+            log_path: None,
+        }
+    }
+}
+
+/// Settings of the song history database.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct HistoryConfig {
+    pub enabled: bool,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> HistoryConfig {
+        HistoryConfig { enabled: true }
+    }
+}
+
+/// Settings of the local HTTP server output.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub enabled: bool,
+    pub port: u16,
+    /// Token that `POST` requests must send in the `X-CurrentSong-Token` header.
+    /// If not set, the header must still be sent, with any value.
+    pub token: Option<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            enabled: false,
+            port: 48458,
+            token: None,
+        }
+    }
+}
+
+/// Settings of the scrobbling output.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct ScrobbleConfig {
+    pub lastfm: LastFmConfig,
+    pub listenbrainz: ListenBrainzConfig,
+    /// Timeout of a single HTTP request, in seconds.
+    pub timeout_secs: u64,
+}
+
+impl Default for ScrobbleConfig {
+    fn default() -> ScrobbleConfig {
+        ScrobbleConfig {
+            lastfm: LastFmConfig::default(),
+            listenbrainz: ListenBrainzConfig::default(),
+            timeout_secs: 10,
+        }
+    }
+}
+
+/// Settings of scrobbling to Last.fm.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct LastFmConfig {
+    pub enabled: bool,
+    /// Key and secret of an API account, see <https://www.last.fm/api/account/create>.
+    pub api_key: String,
+    pub api_secret: String,
+    /// Used to obtain a session key when starting up.
+    pub username: String,
+    pub password: String,
+    /// Endpoint of the API, can be changed to test against a mock server.
+    pub api_url: String,
+}
+
+impl Default for LastFmConfig {
+    fn default() -> LastFmConfig {
+        LastFmConfig {
+            enabled: false,
+            api_key: String::new(),
+            api_secret: String::new(),
+            username: String::new(),
+            password: String::new(),
+            api_url: "https://ws.audioscrobbler.com/2.0/".into(),
+        }
+    }
+}
+
+/// Settings of submitting listens to ListenBrainz.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct ListenBrainzConfig {
+    pub enabled: bool,
+    /// User token from <https://listenbrainz.org/settings/>.
+    pub token: String,
+    /// Root URL of the API, can be changed to test against a mock server.
+    pub api_url: String,
+}
+
+impl Default for ListenBrainzConfig {
+    fn default() -> ListenBrainzConfig {
+        ListenBrainzConfig {
+            enabled: false,
+            token: String::new(),
+            api_url: "https://api.listenbrainz.org".into(),
+        }
+    }
+}
+
+/// Settings of the Discord Rich Presence output.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct DiscordConfig {
+    pub enabled: bool,
+    /// ID of the application registered at <https://discord.com/developers/applications>.
+    /// Its name is shown after "Listening to".
+    pub client_id: String,
+    /// Format of the first line of the activity.
+    pub details: String,
+    /// Format of the second line of the activity.
+    pub state: String,
+    /// Socket or pipe to connect to, instead of looking for a running Discord client.
+    pub ipc_path: Option<PathBuf>,
+}
+
+impl Default for DiscordConfig {
+    fn default() -> DiscordConfig {
+        DiscordConfig {
+            enabled: false,
+            client_id: String::new(),
+            details: "{title}".into(),
+            state: "by {artist}".into(),
+            ipc_path: None,
+        }
+    }
+}
+
+/// Settings of the Twitch chat bot.
+/// Templates are formatted like `song_format`, and may also contain `{user}`,
+/// the chatter who asked, and `{query}`, the artist and title encoded for a URL.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct TwitchConfig {
+    pub enabled: bool,
+    /// Chat server to connect to, can be changed to test against a local server.
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+    /// Sends chat lines in WebSocket messages, e.g. to `irc-ws.chat.twitch.tv` on port 443,
+    /// for networks that block the IRC port.
+    pub websocket: bool,
+    /// Account the bot logs in as.
+    pub username: String,
+    /// OAuth token of the account, with the `chat:read` and `chat:edit` scopes.
+    pub oauth_token: String,
+    /// Channel to join, usually the streamer's username.
+    pub channel: String,
+    /// Answer to `!song`.
+    pub song: String,
+    /// Answer to `!lastsong`.
+    pub last_song: String,
+    /// Answer to `!songlink`.
+    pub song_link: String,
+    /// Answer to `!skip`, which skips to the next song.
+    pub skipped: String,
+    /// Who may use `!skip`.
+    pub skip_allowed: ChatPermission,
+    /// Answer when nothing is playing (or nothing played before).
+    pub nothing_playing: String,
+    /// If set, this message is sent on every song change.
+    pub announce: Option<String>,
+    /// Seconds before the same command is answered again.
+    pub cooldown_secs: u64,
+    /// Seconds before the same chatter gets another answer.
+    pub user_cooldown_secs: u64,
+}
+
+impl Default for TwitchConfig {
+    fn default() -> TwitchConfig {
+        TwitchConfig {
+            enabled: false,
+            host: "irc.chat.twitch.tv".into(),
+            port: 6697,
+            tls: true,
+            websocket: false,
+            username: String::new(),
+            oauth_token: String::new(),
+            channel: String::new(),
+            song: "Now playing: {artist} - {title}".into(),
+            last_song: "Last song: {artist} - {title}".into(),
+            song_link: "{artist} - {title}: https://open.spotify.com/search/{query}".into(),
+            skipped: "Skipping {artist} - {title}".into(),
+            skip_allowed: ChatPermission::Moderators,
+            nothing_playing: "@{user} Nothing is playing right now.".into(),
+            announce: None,
+            cooldown_secs: 10,
+            user_cooldown_secs: 30,
+        }
+    }
+}
+
+/// Who may use a chat command.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChatPermission {
+    Nobody,
+    /// The broadcaster and the moderators of the channel.
+    Moderators,
+    Everyone,
+}
+
+/// Settings of a single webhook output.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// JSON body template. `{artist}`, `{title}`, `{album}` and `{text}` (the song in `song_format`)
+    /// are escaped to be put inside JSON strings, `{state}` is `playing` or `stopped`,
+    /// `{timestamp}` is Unix time and `{json}` is the `nowplaying.json` document.
+    /// The `nowplaying.json` document is sent if not set.
+    #[serde(default)]
+    pub body: Option<String>,
+    /// Extra request headers, e.g. `Authorization`.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// If set, requests are signed with HMAC-SHA256 using this secret.
+    #[serde(default)]
+    pub secret: Option<String>,
+    #[serde(default = "default_signature_header")]
+    pub signature_header: String,
+    /// How many times a failed request is retried before giving up.
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// Timeout of a single request, in seconds.
+    #[serde(default = "default_webhook_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_signature_header() -> String {
+    "X-CurrentSong-Signature".into()
+}
+
+fn default_max_retries() -> u32 {
+    5
+}
+
+fn default_webhook_timeout_secs() -> u64 {
+    10
+}
+
+/// Settings of the control socket, a named pipe on Windows, which lets other programs
+/// such as `currentsong ctl` control a running instance.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct ControlConfig {
+    pub enabled: bool,
+}
+
+impl Default for ControlConfig {
+    fn default() -> ControlConfig {
+        ControlConfig { enabled: true }
+    }
+}
+
+/// Settings of the terminal dashboard, shown instead of plain output
+/// when the app runs in an interactive terminal outside of Windows.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct DashboardConfig {
+    pub enabled: bool,
+    /// How many of the last songs to list.
+    pub recent_songs: usize,
+}
+
+impl Default for DashboardConfig {
+    fn default() -> DashboardConfig {
+        DashboardConfig {
+            enabled: true,
+            recent_songs: 10,
+        }
+    }
+}
+
+/// Settings of desktop notifications, shown on Linux only.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct NotificationConfig {
+    pub enabled: bool,
+    /// Format of the notification's title.
+    pub summary: String,
+    /// Format of the notification's text.
+    pub body: String,
+    /// How long the notification is shown, in milliseconds.
+    /// `-1` leaves it to the notification server and `0` keeps it until dismissed.
+    pub timeout_ms: i32,
+    /// How long a song must play before it is notified about, in seconds,
+    /// so that skipped songs do not show up.
+    pub delay_secs: u64,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> NotificationConfig {
+        NotificationConfig {
+            enabled: false,
+            summary: "{title}".into(),
+            body: "{artist}".into(),
+            timeout_ms: 5000,
+            delay_secs: 0,
+        }
+    }
+}
+
+/// A command run on every song change.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct HookConfig {
+    /// The program to run, followed by its arguments.
+    pub command: Vec<String>,
+    /// Whether to pass the song in `CURRENTSONG_*` environment variables.
+    #[serde(default = "default_true")]
+    pub env: bool,
+    /// Whether to write the `nowplaying.json` document to the standard input.
+    #[serde(default = "default_true")]
+    pub stdin: bool,
+    /// How long the command may run before it is killed, in seconds.
+    #[serde(default = "default_hook_timeout_secs")]
+    pub timeout_secs: u64,
+    /// What to do with a song change while the command is still running.
+    #[serde(default)]
+    pub concurrency: HookConcurrency,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookConcurrency {
+    /// Ignore the change.
+    Skip,
+    /// Run the command again once the previous run exits.
+    #[default]
+    Queue,
+}
+
+fn default_hook_timeout_secs() -> u64 {
+    30
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Settings of the MQTT output.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct MqttConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Defaults to `currentsong-<instance>`.
+    pub client_id: Option<String>,
+    /// Name of this computer in topics, so several instances can share a broker.
+    pub instance: String,
+    pub topic_prefix: String,
+    /// Seconds of inactivity after which the broker considers the app gone.
+    pub keep_alive_secs: u16,
+    /// Whether to publish sensors for Home Assistant's MQTT discovery.
+    pub home_assistant_discovery: bool,
+    pub discovery_prefix: String,
+}
+
+impl Default for MqttConfig {
+    fn default() -> MqttConfig {
+        MqttConfig {
+            enabled: false,
+            host: "localhost".into(),
+            port: 1883,
+            username: None,
+            password: None,
+            client_id: None,
+            instance: "default".into(),
+            topic_prefix: "currentsong".into(),
+            keep_alive_secs: 60,
+            home_assistant_discovery: false,
+            discovery_prefix: "homeassistant".into(),
+        }
+    }
+}
+
+/// Settings of the OBS output, which talks to obs-websocket.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct ObsConfig {
+    pub enabled: bool,
+    /// Address of obs-websocket, which must be `ws://`, as TLS is not supported.
+    #[serde(deserialize_with = "obs_url")]
+    pub url: String,
+    /// Password from "WebSocket Server Settings" in OBS, if authentication is enabled.
+    pub password: Option<String>,
+    /// Text sources to set to the song.
+    pub text_sources: Vec<ObsTextSource>,
+    /// Image source to show the cover art in. Requires the artwork output.
+    pub image_source: Option<String>,
+    /// Scene item shown while a song is playing and hidden otherwise.
+    pub now_playing_item: Option<ObsSceneItem>,
+}
+
+impl Default for ObsConfig {
+    fn default() -> ObsConfig {
+        ObsConfig {
+            enabled: false,
+            url: "ws://127.0.0.1:4455".into(),
+            password: None,
+            text_sources: Vec::new(),
+            image_source: None,
+            now_playing_item: None,
+        }
+    }
+}
+
+/// Reads the obs-websocket address, so that one the OBS output cannot connect to
+/// is reported when the config is read rather than on every connection attempt.
+fn obs_url<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let url = String::deserialize(deserializer)?;
+    match Url::parse(&url).map(|parsed| parsed.scheme().to_owned()) {
+        Ok(scheme) if scheme == "ws" => Ok(url),
+        Ok(scheme) if scheme == "wss" => Err(de::Error::custom(format!(
+            "wss is not supported in obs.url \"{url}\", use ws://"
+        ))),
+        _ => Err(de::Error::custom(format!(
+            "invalid obs.url \"{url}\", expected ws://host:port"
+        ))),
+    }
+}
+
+/// A Text source in OBS.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ObsTextSource {
+    /// Name of the source.
+    pub name: String,
+    /// Format of the song in this source. Uses `song_format` if not set.
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Text shown when nothing is playing.
+    #[serde(default)]
+    pub empty_text: String,
+}
+
+/// A source in a scene.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ObsSceneItem {
+    pub scene: String,
+    pub source: String,
+}
+
+/// Settings of the album art output.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct ArtworkConfig {
+    pub enabled: bool,
+    /// Name of the image file in the data directory.
+    /// The image is written as-is, with the extension of the format it was found in.
+    pub file_name: String,
+    /// Always write to `file_name` as it is, whatever the format of the image,
+    /// so that an image source can point at a single file. Image sources tell the format
+    /// from the content of the file.
+    pub keep_file_name: bool,
+    /// Where to look for art when the song does not come with any.
+    pub lookup: ArtworkLookup,
+    /// Image written when nothing is playing or no art was found.
+    /// A transparent pixel is written if not set.
+    pub placeholder: Option<PathBuf>,
+    /// Largest image that will be downloaded, in kilobytes.
+    pub max_size_kb: u64,
+    /// Timeout of a single HTTP request, in seconds.
+    pub timeout_secs: u64,
+    /// Largest total size of the downloaded art kept in the cache, in megabytes.
+    pub cache_size_mb: u64,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArtworkLookup {
+    None,
+    /// Search MusicBrainz for the release and use its Cover Art Archive front image.
+    CoverArtArchive,
+}
+
+impl Default for ArtworkConfig {
+    fn default() -> ArtworkConfig {
+        ArtworkConfig {
+            enabled: true,
+            file_name: "cover".into(),
+            keep_file_name: false,
+            lookup: ArtworkLookup::None,
+            placeholder: None,
+            max_size_kb: 2048,
+            timeout_secs: 10,
+            cache_size_mb: 64,
         }
     }
 }
@@ -22,30 +756,119 @@ impl Config {
         self.driver.as_str()
     }
 
+    /// Uses a driver other than the one set in the config file, as asked on the command line.
+    pub fn set_driver_name(&mut self, name: String) {
+        self.driver = name;
+    }
+
     pub fn song_format(&self) -> &str {
         self.song_format.as_str()
     }
 
+    /// Whether another config selects the same driver with the same settings.
+    pub fn driver_settings_eq(&self, other: &Config) -> bool {
+        self.driver == other.driver
+            && self.browser_bridge == other.browser_bridge
+            && self.mpd == other.mpd
+            && self.mpris == other.mpris
+            && self.spotify_web == other.spotify_web
+    }
+
+    pub fn browser_bridge(&self) -> &BrowserBridgeConfig {
+        &self.browser_bridge
+    }
+
+    pub fn mpd(&self) -> &MpdConfig {
+        &self.mpd
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn mpris(&self) -> &MprisConfig {
+        &self.mpris
+    }
+
+    pub fn spotify_web(&self) -> &SpotifyWebConfig {
+        &self.spotify_web
+    }
+
+    pub fn plugins(&self) -> &[String] {
+        &self.plugins
+    }
+
+    pub fn files(&self) -> &[FileConfig] {
+        &self.files
+    }
+
+    pub fn json(&self) -> &JsonConfig {
+        &self.json
+    }
+
+    pub fn history(&self) -> &HistoryConfig {
+        &self.history
+    }
+
+    pub fn server(&self) -> &ServerConfig {
+        &self.server
+    }
+
+    pub fn artwork(&self) -> &ArtworkConfig {
+        &self.artwork
+    }
+
+    pub fn scrobble(&self) -> &ScrobbleConfig {
+        &self.scrobble
+    }
+
+    pub fn discord(&self) -> &DiscordConfig {
+        &self.discord
+    }
+
+    pub fn twitch(&self) -> &TwitchConfig {
+        &self.twitch
+    }
+
+    pub fn webhooks(&self) -> &[WebhookConfig] {
+        &self.webhooks
+    }
+
+    pub fn mqtt(&self) -> &MqttConfig {
+        &self.mqtt
+    }
+
+    pub fn obs(&self) -> &ObsConfig {
+        &self.obs
+    }
+
+    pub fn hooks(&self) -> &[HookConfig] {
+        &self.hooks
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn notifications(&self) -> &NotificationConfig {
+        &self.notifications
+    }
+
+    pub fn control(&self) -> &ControlConfig {
+        &self.control
+    }
+
+    #[cfg(unix)]
+    pub fn dashboard(&self) -> &DashboardConfig {
+        &self.dashboard
+    }
+
     /// Attempts to read and deserialize a new [Config] instance
     /// from a file with the provided path.
-    pub fn try_read<P>(path: P) -> Result<Config, Error>
+    /// Settings missing from the file are set to their defaults.
+    pub fn try_read<P>(path: P) -> Result<Config, ConfigError>
     where
         P: AsRef<Path>,
     {
-        match fs::read_to_string(&path) {
-            Ok(config_json) => {
-                let config = serde_json::from_str::<Config>(&config_json)?;
-                Ok(config)
-            }
-            Err(err) => {
-                if err.kind() == ErrorKind::NotFound {
-                    println!("The config file was not found.");
-                } else {
-                    println!("The config file could not be read.");
-                }
-                Err(err.into())
-            }
-        }
+        let config_json = fs::read_to_string(&path).map_err(|err| match err.kind() {
+            ErrorKind::NotFound => ConfigError::Missing,
+            _ => ConfigError::Unreadable(err),
+        })?;
+        serde_json::from_str::<Config>(&config_json).map_err(ConfigError::Invalid)
     }
 
     /// Attempts to serialize this [Config] instance to a file.
@@ -58,3 +881,148 @@ impl Config {
         Ok(())
     }
 }
+
+/// Copies a config file to the same path with `.bak` appended,
+/// so that a copy is kept even if the file gets replaced later.
+/// Earlier copies are never overwritten: a file that differs from them is copied
+/// to `.1.bak`, `.2.bak` and so on, while one that is the same is not copied again.
+pub fn back_up(path: &Path) -> Result<PathBuf, Error> {
+    let content = fs::read(path)?;
+    let mut number = 0;
+    loop {
+        let mut backup = path.as_os_str().to_owned();
+        if number > 0 {
+            backup.push(format!(".{number}"));
+        }
+        backup.push(".bak");
+        let backup = PathBuf::from(backup);
+        match fs::read(&backup) {
+            Ok(existing) if existing == content => return Ok(backup),
+            Ok(_) => number += 1,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                fs::write(&backup, &content)?;
+                return Ok(backup);
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// Why a config file could not be read.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// There is no file at the path yet.
+    Missing,
+    /// The file exists, but cannot be read, e.g. for lack of permissions.
+    Unreadable(io::Error),
+    /// The file is not valid JSON or has a setting of the wrong type.
+    Invalid(serde_json::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Missing => write!(f, "the file does not exist"),
+            ConfigError::Unreadable(err) => write!(f, "{err}"),
+            // serde_json points at the line and column of the mistake
+            ConfigError::Invalid(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates an empty directory for a test to keep its files in.
+    fn test_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("currentsong-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn reports_missing_file() {
+        let directory = test_directory("missing");
+        let result = Config::try_read(directory.join("config.json"));
+        assert!(matches!(result, Err(ConfigError::Missing)));
+    }
+
+    #[test]
+    fn reports_unreadable_file() {
+        // A directory exists at the path, but cannot be read as a file
+        let directory = test_directory("unreadable");
+        let result = Config::try_read(&directory);
+        assert!(matches!(result, Err(ConfigError::Unreadable(_))));
+    }
+
+    #[test]
+    fn reports_invalid_file() {
+        let directory = test_directory("invalid");
+        let path = directory.join("config.json");
+        for json in ["{\"driver\": \"mpd\",}", "{\"plugins\": 5}"] {
+            fs::write(&path, json).unwrap();
+            let result = Config::try_read(&path);
+            assert!(matches!(result, Err(ConfigError::Invalid(_))), "{json}");
+        }
+    }
+
+    #[test]
+    fn rejects_obs_urls_it_cannot_connect_to() {
+        let directory = test_directory("obs-url");
+        let path = directory.join("config.json");
+        fs::write(&path, r#"{"obs": {"url": "ws://127.0.0.1:4455"}}"#).unwrap();
+        assert!(Config::try_read(&path).is_ok());
+        for url in ["wss://127.0.0.1:4455", "127.0.0.1:4455"] {
+            fs::write(&path, format!(r#"{{"obs": {{"url": "{url}"}}}}"#)).unwrap();
+            match Config::try_read(&path) {
+                Err(ConfigError::Invalid(err)) => assert!(err.to_string().contains(url), "{err}"),
+                _ => panic!("{url} should be invalid"),
+            }
+        }
+        fs::write(&path, r#"{"obs": {"url": "wss://obs.local"}}"#).unwrap();
+        let err = Config::try_read(&path).err().unwrap().to_string();
+        assert!(err.starts_with("wss is not supported"), "{err}");
+    }
+
+    #[test]
+    fn fills_in_missing_settings() {
+        let directory = test_directory("partial");
+        let path = directory.join("config.json");
+        fs::write(&path, "{\"driver\": \"mpd\"}").unwrap();
+        let config = Config::try_read(&path).unwrap();
+        assert_eq!(config.driver_name(), "mpd");
+        assert_eq!(config.song_format(), Config::default().song_format());
+    }
+
+    #[test]
+    fn backs_up_next_to_the_file() {
+        let directory = test_directory("backup");
+        let path = directory.join("config.json");
+        fs::write(&path, "{oops").unwrap();
+        let backup = back_up(&path).unwrap();
+        assert_eq!(backup, directory.join("config.json.bak"));
+        assert_eq!(fs::read_to_string(backup).unwrap(), "{oops");
+    }
+
+    #[test]
+    fn keeps_earlier_backups() {
+        let directory = test_directory("backups");
+        let path = directory.join("config.json");
+        fs::write(&path, "{oops").unwrap();
+        back_up(&path).unwrap();
+        // The same file is not copied again
+        assert_eq!(back_up(&path).unwrap(), directory.join("config.json.bak"));
+
+        fs::write(&path, "{oops again").unwrap();
+        let backup = back_up(&path).unwrap();
+        assert_eq!(backup, directory.join("config.json.1.bak"));
+        assert_eq!(fs::read_to_string(backup).unwrap(), "{oops again");
+        let first = directory.join("config.json.bak");
+        assert_eq!(fs::read_to_string(first).unwrap(), "{oops");
+    }
+}
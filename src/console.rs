@@ -1,14 +1,62 @@
-use std::sync::Arc;
+use std::{
+    io::{stdout, Write},
+    str::FromStr,
+    sync::Arc,
+};
 
-use crate::{config::Config, song::SongInfo, Actor, ActorHandle};
+use anyhow::{anyhow, Error};
+
+use crate::{config::Config, json::NowPlaying, song::SongInfo, Actor, ActorHandle};
+
+/// How song changes are written to standard output.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// `Now: Artist - Title` lines, for people.
+    Text,
+    /// One `nowplaying.json` document per line, for other programs.
+    Jsonl,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "jsonl" | "json-lines" => Ok(OutputFormat::Jsonl),
+            _ => Err(anyhow!("unknown output format: {s}")),
+        }
+    }
+}
 
 pub struct ConsoleActor {
+    format: OutputFormat,
     config: Arc<Config>,
 }
 
 impl ConsoleActor {
-    pub fn new(config: Arc<Config>) -> Self {
-        Self { config }
+    pub fn new(format: OutputFormat, config: Arc<Config>) -> Self {
+        Self { format, config }
+    }
+
+    fn print(&self, song: Option<&SongInfo>) {
+        match (self.format, song) {
+            (OutputFormat::Text, Some(song)) => {
+                let song_str = song.format(self.config.song_format());
+                println!("Now: {}", song_str);
+            }
+            (OutputFormat::Text, None) => {
+                println!("Now: ---");
+            }
+            (OutputFormat::Jsonl, song) => match serde_json::to_string(&NowPlaying::new(song)) {
+                Ok(line) => {
+                    let mut stdout = stdout().lock();
+                    // Readers on the other end of a pipe expect every line as soon as it happens
+                    let _ = writeln!(stdout, "{line}").and_then(|_| stdout.flush());
+                }
+                Err(err) => warn!("  | Cannot serialize song: {err:?}"),
+            },
+        }
     }
 }
 
@@ -18,20 +66,9 @@ impl Actor for ConsoleActor {
         let (sender, receiver) = flume::unbounded();
         ActorHandle {
             sender,
-            thread_handle: std::thread::spawn(move || loop {
-                match receiver.recv() {
-                    Ok(Some(song)) => {
-                        let format = self.config.song_format();
-                        let song_str = format
-                            .replace("{artist}", &song.artist)
-                            .replace("{title}", &song.title);
-
-                        println!("Now: {}", song_str);
-                    }
-                    Ok(None) => {
-                        println!("Now: ---");
-                    }
-                    _ => break,
+            thread_handle: std::thread::spawn(move || {
+                while let Ok(song) = receiver.recv() {
+                    self.print(song.as_ref());
                 }
             }),
         }
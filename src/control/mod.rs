@@ -0,0 +1,254 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Error};
+use flume::Sender;
+use serde_json::{json, Value};
+
+use crate::{
+    app::LifecycleEvent,
+    driver::PlayerCommand,
+    history::SessionMarker,
+    song_override::{parse_duration, SongOverride},
+    status::Status,
+};
+
+#[cfg(unix)]
+mod unix;
+#[cfg(target_os = "windows")]
+mod windows;
+
+#[cfg(unix)]
+use unix::{connect, Listener};
+#[cfg(target_os = "windows")]
+use windows::{connect, Listener};
+
+/// How long to wait before accepting connections again after a failure.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Listens for commands from other programs on a local socket (a named pipe on Windows),
+/// one command per line. Every command is answered with a line of JSON.
+pub struct ControlServer {
+    data_directory: PathBuf,
+    status: Arc<Mutex<Status>>,
+    lifecycle_sender: Sender<LifecycleEvent>,
+}
+
+impl ControlServer {
+    pub fn new(
+        data_directory: PathBuf,
+        status: Arc<Mutex<Status>>,
+        lifecycle_sender: Sender<LifecycleEvent>,
+    ) -> Self {
+        Self {
+            data_directory,
+            status,
+            lifecycle_sender,
+        }
+    }
+
+    /// Starts accepting connections in the background.
+    /// If the socket cannot be opened, the app runs without it.
+    pub fn start(self) {
+        let mut listener = match Listener::bind(&self.data_directory) {
+            Ok(listener) => listener,
+            Err(err) => {
+                warn!("  | Cannot open the control socket: {err}");
+                return;
+            }
+        };
+        thread::spawn(move || loop {
+            match listener.accept() {
+                Ok(connection) => {
+                    let status = self.status.clone();
+                    let lifecycle_sender = self.lifecycle_sender.clone();
+                    thread::spawn(move || serve(connection, &status, &lifecycle_sender));
+                }
+                Err(err) => {
+                    warn!("  | Cannot accept a control connection: {err}");
+                    thread::sleep(RETRY_DELAY);
+                }
+            }
+        });
+    }
+}
+
+/// Answers commands sent over a connection until it is closed.
+fn serve<C>(connection: C, status: &Mutex<Status>, lifecycle_sender: &Sender<LifecycleEvent>)
+where
+    for<'a> &'a C: Read + Write,
+{
+    let mut writer = &connection;
+    for line in BufReader::new(&connection).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match execute(&line, status, lifecycle_sender) {
+            Ok(reply) => reply,
+            Err(err) => json!({ "ok": false, "error": err.to_string() }),
+        };
+        if writeln!(writer, "{reply}").is_err() {
+            break;
+        }
+    }
+}
+
+/// Runs a command such as `pause`, `override Artist - Title` or `player seek 90`.
+fn execute(
+    line: &str,
+    status: &Mutex<Status>,
+    lifecycle_sender: &Sender<LifecycleEvent>,
+) -> Result<Value, Error> {
+    let line = line.trim();
+    let (name, argument) = line.split_once(' ').unwrap_or((line, ""));
+    let argument = argument.trim();
+    let event = match name.to_lowercase().as_str() {
+        "status" => {
+            let status = status.lock().unwrap().clone();
+            return Ok(json!({ "ok": true, "status": status }));
+        }
+        "refresh" => LifecycleEvent::Refresh,
+        "pause" => LifecycleEvent::Pause,
+        "resume" => LifecycleEvent::Resume,
+        "override" => LifecycleEvent::Override(Some(parse_override(argument)?)),
+        "clear-override" => LifecycleEvent::Override(None),
+        "reload" => {
+            // Reloading can fail, so wait for the result
+            let (sender, receiver) = flume::bounded(1);
+            send(lifecycle_sender, LifecycleEvent::Reload(Some(sender)))?;
+            receiver
+                .recv()
+                .map_err(|_| anyhow!("the app is shutting down"))??;
+            return Ok(json!({ "ok": true }));
+        }
+        "exit" | "quit" => LifecycleEvent::Exit,
+        "player" => LifecycleEvent::Player(PlayerCommand::from_str(argument)?),
+        "session" => match argument.to_lowercase().as_str() {
+            "start" => LifecycleEvent::Session(SessionMarker::Start),
+            "end" => LifecycleEvent::Session(SessionMarker::End),
+            _ => return Err(anyhow!("session requires start or end")),
+        },
+        _ => return Err(anyhow!("unknown command: \"{line}\"")),
+    };
+    send(lifecycle_sender, event)?;
+    Ok(json!({ "ok": true }))
+}
+
+/// Parses the argument of the `override` command: text, optionally preceded by `--for DURATION`.
+fn parse_override(argument: &str) -> Result<SongOverride, Error> {
+    let (duration, text) = match argument.strip_prefix("--for ") {
+        Some(rest) => {
+            let (duration, text) = rest.trim_start().split_once(' ').unwrap_or((rest, ""));
+            (Some(parse_duration(duration)?), text.trim())
+        }
+        None => (None, argument),
+    };
+    if text.is_empty() {
+        return Err(anyhow!(
+            "override requires text, use clear-override to show the song again"
+        ));
+    }
+    SongOverride::new(text.to_string(), duration)
+}
+
+fn send(lifecycle_sender: &Sender<LifecycleEvent>, event: LifecycleEvent) -> Result<(), Error> {
+    lifecycle_sender
+        .send(event)
+        .map_err(|_| anyhow!("the app is shutting down"))
+}
+
+/// Sends a command to a running instance and returns its reply.
+pub fn request(data_directory: &Path, command: &str) -> Result<Value, Error> {
+    let connection = connect(data_directory)
+        .map_err(|err| anyhow!("cannot connect to a running instance: {err}"))?;
+    writeln!(&connection, "{}", command.trim())?;
+    let mut reply = String::new();
+    BufReader::new(&connection).read_line(&mut reply)?;
+    if reply.is_empty() {
+        // The app may shut down before it gets to reply
+        if matches!(command.trim().to_lowercase().as_str(), "exit" | "quit") {
+            return Ok(json!({ "ok": true }));
+        }
+        return Err(anyhow!("the running instance closed the connection"));
+    }
+    Ok(serde_json::from_str(&reply)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a command line, returning the reply and the event it sent to the app, if any.
+    fn run(line: &str) -> (Result<Value, Error>, Option<LifecycleEvent>) {
+        let status = Mutex::new(Status::default());
+        let (sender, receiver) = flume::unbounded();
+        let reply = execute(line, &status, &sender);
+        (reply, receiver.try_recv().ok())
+    }
+
+    #[test]
+    fn sends_simple_commands_to_the_app() {
+        let (reply, event) = run("  PAUSE ");
+        assert_eq!(reply.unwrap(), json!({ "ok": true }));
+        assert!(matches!(event, Some(LifecycleEvent::Pause)));
+        assert!(matches!(run("resume").1, Some(LifecycleEvent::Resume)));
+        assert!(matches!(run("quit").1, Some(LifecycleEvent::Exit)));
+        assert!(matches!(
+            run("clear-override").1,
+            Some(LifecycleEvent::Override(None))
+        ));
+    }
+
+    #[test]
+    fn parses_command_arguments() {
+        assert!(matches!(
+            run("player seek 90").1,
+            Some(LifecycleEvent::Player(PlayerCommand::Seek { seconds })) if seconds == 90.0
+        ));
+        assert!(matches!(
+            run("session End").1,
+            Some(LifecycleEvent::Session(SessionMarker::End))
+        ));
+    }
+
+    #[test]
+    fn reports_invalid_commands_without_sending_them() {
+        for line in ["dance", "session maybe", "player jump", "override"] {
+            let (reply, event) = run(line);
+            assert!(reply.is_err(), "{line:?} should fail");
+            assert!(event.is_none(), "{line:?} should not be sent");
+        }
+    }
+
+    #[test]
+    fn answers_status_itself() {
+        let (reply, event) = run("status");
+        let reply = reply.unwrap();
+        assert_eq!(reply["ok"], true);
+        assert!(reply["status"].is_object());
+        assert!(event.is_none());
+    }
+
+    #[test]
+    fn parses_override_text_and_duration() {
+        let song_override = parse_override("Artist - Title").unwrap();
+        assert_eq!(song_override.text, "Artist - Title");
+        assert_eq!(song_override.expires_at, None);
+
+        let song_override = parse_override("--for 15m BRB - lofi beats").unwrap();
+        assert_eq!(song_override.text, "BRB - lofi beats");
+        assert!(song_override.expires_at.is_some());
+
+        assert!(parse_override("--for 15m").is_err());
+        assert!(parse_override("--for soon BRB").is_err());
+    }
+}
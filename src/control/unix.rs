@@ -0,0 +1,39 @@
+use std::{
+    fs,
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Error};
+
+const SOCKET_FILE_NAME: &str = "control.sock";
+
+fn socket_path(data_directory: &Path) -> PathBuf {
+    data_directory.join(SOCKET_FILE_NAME)
+}
+
+/// A Unix domain socket in the data directory.
+pub struct Listener(UnixListener);
+
+impl Listener {
+    pub fn bind(data_directory: &Path) -> Result<Listener, Error> {
+        let path = socket_path(data_directory);
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                return Err(anyhow!("another instance is listening on {path:?}"));
+            }
+            // Left behind by an instance that did not shut down cleanly
+            fs::remove_file(&path)?;
+        }
+        Ok(Listener(UnixListener::bind(&path)?))
+    }
+
+    pub fn accept(&mut self) -> Result<UnixStream, Error> {
+        let (stream, _) = self.0.accept()?;
+        Ok(stream)
+    }
+}
+
+pub fn connect(data_directory: &Path) -> Result<UnixStream, Error> {
+    Ok(UnixStream::connect(socket_path(data_directory))?)
+}
@@ -0,0 +1,93 @@
+use std::{
+    env,
+    ffi::OsStr,
+    fs::{File, OpenOptions},
+    io,
+    os::windows::{ffi::OsStrExt, io::FromRawHandle},
+    path::Path,
+    ptr,
+};
+
+use anyhow::Error;
+use windows_sys::Win32::{
+    Foundation::{ERROR_PIPE_CONNECTED, HANDLE, INVALID_HANDLE_VALUE},
+    Storage::FileSystem::{FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX},
+    System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS,
+        PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+    },
+};
+
+const BUFFER_SIZE: u32 = 4096;
+
+/// Named pipes are shared by the whole machine, so every user gets their own.
+fn pipe_name() -> String {
+    let user = env::var("USERNAME").unwrap_or_default();
+    format!(r"\\.\pipe\Frixuu.CurrentSong.{user}")
+}
+
+/// A named pipe, with a new instance created for every connection.
+pub struct Listener {
+    name: Vec<u16>,
+    /// The instance to accept the next connection on, if already created.
+    next: Option<HANDLE>,
+}
+
+impl Listener {
+    /// Creates the first instance of the pipe, which fails if another app has created it already.
+    pub fn bind(_data_directory: &Path) -> Result<Listener, Error> {
+        let name = OsStr::new(&pipe_name())
+            .encode_wide()
+            .chain(Some(0))
+            .collect::<Vec<_>>();
+        let first = create_instance(&name, FILE_FLAG_FIRST_PIPE_INSTANCE)?;
+        Ok(Listener {
+            name,
+            next: Some(first),
+        })
+    }
+
+    pub fn accept(&mut self) -> Result<File, Error> {
+        let handle = match self.next.take() {
+            Some(handle) => handle,
+            None => create_instance(&self.name, 0)?,
+        };
+        // Closes the instance if connecting fails
+        let file = unsafe { File::from_raw_handle(handle as _) };
+        let connected = unsafe { ConnectNamedPipe(handle, ptr::null_mut()) } != 0;
+        if !connected {
+            let err = io::Error::last_os_error();
+            // The client may have connected between creating the instance and waiting for it
+            if err.raw_os_error() != Some(ERROR_PIPE_CONNECTED as i32) {
+                return Err(err.into());
+            }
+        }
+        Ok(file)
+    }
+}
+
+fn create_instance(name: &[u16], flags: u32) -> Result<HANDLE, Error> {
+    let handle = unsafe {
+        CreateNamedPipeW(
+            name.as_ptr(),
+            PIPE_ACCESS_DUPLEX | flags,
+            PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+            PIPE_UNLIMITED_INSTANCES,
+            BUFFER_SIZE,
+            BUFFER_SIZE,
+            0,
+            ptr::null(),
+        )
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(handle)
+}
+
+pub fn connect(_data_directory: &Path) -> Result<File, Error> {
+    Ok(OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(pipe_name())?)
+}
@@ -0,0 +1,449 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    os::fd::FromRawFd,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use flume::{Receiver, Sender, TryRecvError};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::Stylize,
+    text::{Line, Span},
+    widgets::{Block, Gauge, List, ListItem, Paragraph},
+    Frame,
+};
+
+use crate::{
+    app::LifecycleEvent, config::Config, driver::PlayerCommand, history::History, json::unix_time,
+    song::SongInfo, song_override::SongOverride, status::Status, Actor, ActorHandle,
+};
+
+/// How often the dashboard is redrawn, to keep the progress bar moving.
+const TICK: Duration = Duration::from_millis(250);
+
+/// How many lines of diagnostics are kept.
+const LOG_SIZE: usize = 200;
+
+/// Redirects the standard error of the whole process into a pipe while the dashboard is shown,
+/// as diagnostics printed from other threads would garble the screen.
+struct StderrCapture {
+    /// Duplicate of the original standard error, put back on drop.
+    original: i32,
+}
+
+impl StderrCapture {
+    /// Starts capturing, returning a receiver for the captured lines.
+    fn start() -> io::Result<(StderrCapture, Receiver<String>)> {
+        let mut fds = [0; 2];
+        unsafe {
+            if libc::pipe(fds.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let original = libc::dup(libc::STDERR_FILENO);
+            if original < 0 || libc::dup2(fds[1], libc::STDERR_FILENO) < 0 {
+                let err = io::Error::last_os_error();
+                libc::close(fds[0]);
+                libc::close(fds[1]);
+                return Err(err);
+            }
+            libc::close(fds[1]);
+
+            let reader = File::from_raw_fd(fds[0]);
+            let (sender, receiver) = flume::unbounded();
+            // Ends once standard error is put back, which closes the pipe
+            thread::spawn(move || {
+                for line in BufReader::new(reader).lines() {
+                    let Ok(line) = line else { break };
+                    let _ = sender.send(line);
+                }
+            });
+            Ok((StderrCapture { original }, receiver))
+        }
+    }
+}
+
+impl Drop for StderrCapture {
+    fn drop(&mut self) {
+        unsafe {
+            libc::dup2(self.original, libc::STDERR_FILENO);
+            libc::close(self.original);
+        }
+    }
+}
+
+/// A song shown in the list of recent songs.
+struct RecentSong {
+    /// Unix time the song started playing.
+    started_at: u64,
+    text: String,
+}
+
+impl RecentSong {
+    fn new(started_at: u64, artist: &str, title: &str) -> Self {
+        let text = if artist.is_empty() {
+            title.to_string()
+        } else {
+            format!("{artist} - {title}")
+        };
+        Self { started_at, text }
+    }
+}
+
+/// An actor that takes over the terminal with a dashboard: the current song and its progress,
+/// the driver, the outputs and recent songs. Keys control the app and the media player.
+pub struct DashboardActor {
+    data_directory: PathBuf,
+    status: Arc<Mutex<Status>>,
+    lifecycle_sender: Sender<LifecycleEvent>,
+    config: Arc<Config>,
+}
+
+/// State of the dashboard between frames.
+struct Dashboard {
+    status: Status,
+    recent: VecDeque<RecentSong>,
+    log: VecDeque<String>,
+    /// Text being typed as the override, if the prompt is open.
+    input: Option<String>,
+}
+
+impl DashboardActor {
+    pub fn new(
+        data_directory: PathBuf,
+        status: Arc<Mutex<Status>>,
+        lifecycle_sender: Sender<LifecycleEvent>,
+        config: Arc<Config>,
+    ) -> Self {
+        Self {
+            data_directory,
+            status,
+            lifecycle_sender,
+            config,
+        }
+    }
+
+    /// Lists songs from previous runs, if the song history is enabled.
+    fn load_recent(&self) -> VecDeque<RecentSong> {
+        if !self.config.history().enabled {
+            return VecDeque::new();
+        }
+        let count = self.config.dashboard().recent_songs as u32;
+        History::open(&self.data_directory)
+            .and_then(|history| history.last(count))
+            .map(|plays| {
+                plays
+                    .iter()
+                    .map(|play| RecentSong::new(play.started_at as u64, &play.artist, &play.title))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn send(&self, event: LifecycleEvent) {
+        let _ = self.lifecycle_sender.send(event);
+    }
+
+    /// Handles a key press.
+    fn on_key(&self, dashboard: &mut Dashboard, key: KeyEvent) {
+        // The terminal is in raw mode, so Ctrl+C does not interrupt the app by itself
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            self.send(LifecycleEvent::Exit);
+            return;
+        }
+
+        if let Some(input) = &mut dashboard.input {
+            match key.code {
+                KeyCode::Enter => {
+                    let text = dashboard.input.take().unwrap_or_default();
+                    let song_override = Some(text)
+                        .filter(|text| !text.trim().is_empty())
+                        // Text without a duration never fails to be set
+                        .and_then(|text| SongOverride::new(text, None).ok());
+                    self.send(LifecycleEvent::Override(song_override));
+                }
+                KeyCode::Esc => dashboard.input = None,
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                _ => {}
+            }
+            return;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.send(LifecycleEvent::Exit),
+            KeyCode::Char('p') => self.send(if dashboard.status.paused {
+                LifecycleEvent::Resume
+            } else {
+                LifecycleEvent::Pause
+            }),
+            KeyCode::Char('o') => {
+                let song_override = dashboard.status.song_override.as_ref();
+                dashboard.input = Some(song_override.map(|o| o.text.clone()).unwrap_or_default());
+            }
+            KeyCode::Char('r') => self.send(LifecycleEvent::Reload(None)),
+            KeyCode::Char(' ') => self.send(LifecycleEvent::Player(PlayerCommand::Toggle)),
+            KeyCode::Char('n') => self.send(LifecycleEvent::Player(PlayerCommand::Next)),
+            KeyCode::Char('b') => self.send(LifecycleEvent::Player(PlayerCommand::Previous)),
+            _ => {}
+        }
+    }
+}
+
+impl Dashboard {
+    fn draw(&self, frame: &mut Frame) {
+        let [now_playing, middle, bottom, footer] = Layout::vertical([
+            Constraint::Length(5),
+            Constraint::Min(6),
+            Constraint::Length(12),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [status, outputs] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .areas(middle);
+        let [recent, log] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .areas(bottom);
+
+        self.draw_now_playing(frame, now_playing);
+        self.draw_status(frame, status);
+        self.draw_outputs(frame, outputs);
+        self.draw_recent(frame, recent);
+        self.draw_log(frame, log);
+        self.draw_footer(frame, footer);
+    }
+
+    fn draw_now_playing(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title(" Now playing ");
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+        let [title, details, progress] = Layout::vertical([Constraint::Length(1); 3]).areas(inner);
+
+        let Some(song) = &self.status.song else {
+            frame.render_widget(Paragraph::new("Nothing is playing".dim()), title);
+            return;
+        };
+        frame.render_widget(Paragraph::new(song.title.as_str().bold()), title);
+        let mut line = vec![Span::raw(song.artist.as_str())];
+        if let Some(album) = &song.album {
+            line.push(" · ".dim());
+            line.push(Span::raw(album.as_str()));
+        }
+        frame.render_widget(Paragraph::new(Line::from(line)), details);
+
+        // The driver does not report the position, so count from when the song was first seen
+        let elapsed = self
+            .status
+            .song_since
+            .and_then(|since| since.elapsed().ok())
+            .map(|elapsed| elapsed.as_secs_f64())
+            .unwrap_or_default();
+        let (ratio, label) = match song.duration {
+            Some(duration) if duration > 0.0 => (
+                (elapsed / duration).clamp(0.0, 1.0),
+                format!("{} / {}", clock(elapsed), clock(duration)),
+            ),
+            _ => (0.0, clock(elapsed)),
+        };
+        frame.render_widget(Gauge::default().ratio(ratio).label(label), progress);
+    }
+
+    fn draw_status(&self, frame: &mut Frame, area: Rect) {
+        let status = &self.status;
+        let last_fetch = status
+            .last_fetch
+            .and_then(|time| time.elapsed().ok())
+            .map(|elapsed| format!("{}s ago", elapsed.as_secs()))
+            .unwrap_or_else(|| "never".into());
+        let outputs = if status.paused {
+            "paused".yellow()
+        } else {
+            "live".green()
+        };
+        let override_text = match &status.song_override {
+            Some(song_override) => match song_override.expires_at {
+                Some(expires_at) => {
+                    let left = expires_at.saturating_sub(unix_time());
+                    format!("{} ({} left)", song_override.text, clock(left as f64)).yellow()
+                }
+                None => song_override.text.as_str().yellow(),
+            },
+            None => "none".dim(),
+        };
+        let lines = vec![
+            Line::from(vec!["Driver:   ".dim(), Span::raw(status.driver.as_str())]),
+            Line::from(vec!["Fetched:  ".dim(), Span::raw(last_fetch)]),
+            Line::from(vec!["Outputs:  ".dim(), outputs]),
+            Line::from(vec!["Override: ".dim(), override_text]),
+        ];
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" Status ")),
+            area,
+        );
+    }
+
+    fn draw_outputs(&self, frame: &mut Frame, area: Rect) {
+        let items = self.status.outputs.iter().map(|output| {
+            let state = if output.running {
+                "● ".green()
+            } else {
+                "✗ ".red()
+            };
+            let mut line = vec![state, Span::raw(output.name.as_str())];
+            if !output.running {
+                line.push(" (stopped)".red());
+            }
+            ListItem::new(Line::from(line))
+        });
+        frame.render_widget(
+            List::new(items).block(Block::bordered().title(" Outputs ")),
+            area,
+        );
+    }
+
+    fn draw_recent(&self, frame: &mut Frame, area: Rect) {
+        let now = unix_time();
+        let items = self.recent.iter().map(|song| {
+            let ago = format!("{:>7} ", ago(now.saturating_sub(song.started_at)));
+            ListItem::new(Line::from(vec![ago.dim(), Span::raw(song.text.as_str())]))
+        });
+        frame.render_widget(
+            List::new(items).block(Block::bordered().title(" Recent ")),
+            area,
+        );
+    }
+
+    fn draw_log(&self, frame: &mut Frame, area: Rect) {
+        let height = area.height.saturating_sub(2) as usize;
+        let items = self
+            .log
+            .iter()
+            .skip(self.log.len().saturating_sub(height))
+            .map(|line| ListItem::new(line.as_str()));
+        frame.render_widget(
+            List::new(items).block(Block::bordered().title(" Log ")),
+            area,
+        );
+    }
+
+    fn draw_footer(&self, frame: &mut Frame, area: Rect) {
+        let line = match &self.input {
+            Some(input) => Line::from(vec![
+                " Override (\"Artist - Title\", empty to clear): ".bold(),
+                Span::raw(input.as_str()),
+                "█".dim(),
+                "  Enter set · Esc cancel".dim(),
+            ]),
+            None => {
+                let pause = if self.status.paused {
+                    "resume"
+                } else {
+                    "pause"
+                };
+                Line::from(
+                    format!(
+                        " p {pause} outputs · o override · r reload config · \
+                         space play/pause · n next · b previous · q quit"
+                    )
+                    .dim(),
+                )
+            }
+        };
+        frame.render_widget(Paragraph::new(line), area);
+    }
+}
+
+/// Formats seconds as `m:ss`.
+fn clock(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Formats an age in seconds, e.g. `5 min`.
+fn ago(seconds: u64) -> String {
+    match seconds {
+        0..=59 => "now".into(),
+        60..=3599 => format!("{} min", seconds / 60),
+        3600..=86399 => format!("{} h", seconds / 3600),
+        _ => format!("{} d", seconds / 86400),
+    }
+}
+
+impl Actor for DashboardActor {
+    type MessageType = Option<SongInfo>;
+    fn spawn(self) -> ActorHandle<Self::MessageType> {
+        let (sender, receiver) = flume::unbounded::<Option<SongInfo>>();
+        ActorHandle {
+            sender,
+            thread_handle: thread::spawn(move || {
+                let recent_songs = self.config.dashboard().recent_songs;
+                let mut dashboard = Dashboard {
+                    status: Status::default(),
+                    recent: self.load_recent(),
+                    log: VecDeque::new(),
+                    input: None,
+                };
+                let capture = StderrCapture::start();
+                let log_receiver = match &capture {
+                    Ok((_, receiver)) => Some(receiver.clone()),
+                    Err(err) => {
+                        dashboard
+                            .log
+                            .push_back(format!("Cannot capture diagnostics: {err}"));
+                        None
+                    }
+                };
+                let mut terminal = ratatui::init();
+
+                loop {
+                    match receiver.try_recv() {
+                        Ok(Some(song)) => {
+                            let recent = RecentSong::new(unix_time(), &song.artist, &song.title);
+                            dashboard.recent.push_front(recent);
+                            dashboard.recent.truncate(recent_songs);
+                            continue;
+                        }
+                        Ok(None) => continue,
+                        Err(TryRecvError::Empty) => {}
+                        Err(TryRecvError::Disconnected) => break,
+                    }
+                    if let Some(log_receiver) = &log_receiver {
+                        dashboard.log.extend(log_receiver.drain());
+                        let excess = dashboard.log.len().saturating_sub(LOG_SIZE);
+                        dashboard.log.drain(..excess);
+                    }
+                    dashboard.status = self.status.lock().unwrap().clone();
+
+                    if let Err(err) = terminal.draw(|frame| dashboard.draw(frame)) {
+                        dashboard
+                            .log
+                            .push_back(format!("Cannot draw the dashboard: {err}"));
+                    }
+                    if let Ok(true) = event::poll(TICK) {
+                        if let Ok(Event::Key(key)) = event::read() {
+                            if key.kind == KeyEventKind::Press {
+                                self.on_key(&mut dashboard, key);
+                            }
+                        }
+                    }
+                }
+
+                ratatui::restore();
+                drop(capture);
+                // Keep the diagnostics visible after the dashboard is gone
+                let mut stderr = io::stderr();
+                for line in &dashboard.log {
+                    let _ = writeln!(stderr, "{line}");
+                }
+            }),
+        }
+    }
+}
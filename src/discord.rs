@@ -0,0 +1,261 @@
+use std::{
+    io::{Read, Write},
+    path::PathBuf,
+    process,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Error};
+use flume::RecvTimeoutError;
+use serde_json::{json, Value};
+
+use crate::{
+    config::{Config, DiscordConfig},
+    json::unix_time,
+    song::SongInfo,
+    Actor, ActorHandle,
+};
+
+/// How often to try connecting while Discord is not running,
+/// and to refresh the activity while it is, to notice Discord restarting.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How long to wait for Discord to answer. It answers every command right away.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Largest frame Discord is expected to send.
+const MAX_FRAME_SIZE: u32 = 64 * 1024;
+
+const OP_HANDSHAKE: u32 = 0;
+const OP_FRAME: u32 = 1;
+const OP_CLOSE: u32 = 2;
+const OP_PING: u32 = 3;
+const OP_PONG: u32 = 4;
+
+/// Type of a "Listening to" activity.
+const ACTIVITY_LISTENING: u32 = 2;
+
+trait IpcStream: Read + Write + Send {}
+impl<T: Read + Write + Send> IpcStream for T {}
+
+/// A connection to the Discord client's local RPC socket.
+struct Connection {
+    stream: Box<dyn IpcStream>,
+    nonce: u64,
+}
+
+impl Connection {
+    /// Connects to the first Discord instance found and performs the handshake.
+    fn open(config: &DiscordConfig) -> Result<Connection, Error> {
+        let paths = match &config.ipc_path {
+            Some(path) => vec![path.clone()],
+            None => ipc_paths(),
+        };
+        let stream = paths
+            .iter()
+            .find_map(|path| connect(path).ok())
+            .ok_or_else(|| anyhow!("Discord is not running"))?;
+
+        let mut connection = Connection { stream, nonce: 0 };
+        connection.send(
+            OP_HANDSHAKE,
+            &json!({ "v": 1, "client_id": config.client_id }),
+        )?;
+        let ready = connection.receive()?;
+        if ready["evt"] != "READY" {
+            return Err(anyhow!("handshake failed: {ready}"));
+        }
+        Ok(connection)
+    }
+
+    fn send(&mut self, opcode: u32, payload: &Value) -> Result<(), Error> {
+        let payload = payload.to_string();
+        let mut frame = Vec::with_capacity(8 + payload.len());
+        frame.extend_from_slice(&opcode.to_le_bytes());
+        frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        frame.extend_from_slice(payload.as_bytes());
+        self.stream.write_all(&frame)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    /// Reads the next message, answering pings on the way.
+    fn receive(&mut self) -> Result<Value, Error> {
+        loop {
+            let mut header = [0; 8];
+            self.stream.read_exact(&mut header)?;
+            let opcode = u32::from_le_bytes(header[..4].try_into().unwrap());
+            let length = u32::from_le_bytes(header[4..].try_into().unwrap());
+            if length > MAX_FRAME_SIZE {
+                return Err(anyhow!("frame too large: {length} bytes"));
+            }
+            let mut payload = vec![0; length as usize];
+            self.stream.read_exact(&mut payload)?;
+            let payload: Value = serde_json::from_slice(&payload)?;
+            match opcode {
+                OP_FRAME => return Ok(payload),
+                OP_PING => self.send(OP_PONG, &payload)?,
+                OP_CLOSE => return Err(anyhow!("closed by Discord: {payload}")),
+                _ => {}
+            }
+        }
+    }
+
+    /// Sets the activity shown on the user's profile, or clears it if `None`.
+    fn set_activity(&mut self, activity: Option<&Value>) -> Result<(), Error> {
+        self.nonce += 1;
+        let command = json!({
+            "cmd": "SET_ACTIVITY",
+            "args": { "pid": process::id(), "activity": activity },
+            "nonce": self.nonce.to_string(),
+        });
+        self.send(OP_FRAME, &command)?;
+        let response = self.receive()?;
+        if response["evt"] == "ERROR" {
+            return Err(anyhow!("{}", response["data"]["message"]));
+        }
+        Ok(())
+    }
+}
+
+/// Paths Discord may listen on. It takes the first free one of ten.
+fn ipc_paths() -> Vec<PathBuf> {
+    #[cfg(target_os = "windows")]
+    let directories = [PathBuf::from(r"\\?\pipe")];
+    #[cfg(not(target_os = "windows"))]
+    let directories = ["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEMP"]
+        .into_iter()
+        .filter_map(std::env::var_os)
+        .map(PathBuf::from)
+        .chain([PathBuf::from("/tmp")])
+        .collect::<Vec<_>>();
+
+    directories
+        .iter()
+        .flat_map(|directory| (0..10).map(move |i| directory.join(format!("discord-ipc-{i}"))))
+        .collect()
+}
+
+#[cfg(target_os = "windows")]
+fn connect(path: &std::path::Path) -> std::io::Result<Box<dyn IpcStream>> {
+    let pipe = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)?;
+    Ok(Box::new(pipe))
+}
+
+#[cfg(not(target_os = "windows"))]
+fn connect(path: &std::path::Path) -> std::io::Result<Box<dyn IpcStream>> {
+    let socket = std::os::unix::net::UnixStream::connect(path)?;
+    // Discord answers every command right away, do not hang if it stops responding
+    socket.set_read_timeout(Some(RESPONSE_TIMEOUT))?;
+    Ok(Box::new(socket))
+}
+
+/// Talks to Discord on a helper thread, giving up after [RESPONSE_TIMEOUT].
+/// Reads from a named pipe cannot time out on Windows, so a Discord client that stopped
+/// responding would otherwise block the actor, which could then neither update nor stop.
+fn with_timeout<T, F>(call: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+{
+    let (sender, receiver) = flume::bounded(1);
+    thread::spawn(move || {
+        let _ = sender.send(call());
+    });
+    receiver
+        .recv_timeout(RESPONSE_TIMEOUT)
+        .map_err(|_| anyhow!("Discord did not respond"))?
+}
+
+/// An actor that shows the song as a "Listening to" activity on the user's Discord profile.
+pub struct DiscordActor {
+    config: Arc<Config>,
+}
+
+impl DiscordActor {
+    pub fn new(config: Arc<Config>) -> Self {
+        Self { config }
+    }
+
+    fn activity(&self, song: &SongInfo, started_at: u64) -> Value {
+        let discord_config = self.config.discord();
+        let mut activity = json!({
+            "type": ACTIVITY_LISTENING,
+            "details": field(&song.format(&discord_config.details)),
+            "state": field(&song.format(&discord_config.state)),
+            "timestamps": { "start": started_at * 1000 },
+        });
+        if let Some(duration) = song.duration {
+            let end = started_at * 1000 + (duration * 1000.0) as u64;
+            activity["timestamps"]["end"] = json!(end);
+        }
+        // Discord proxies images from external URLs, but not local files
+        let artwork_url = song
+            .artwork_url
+            .as_deref()
+            .filter(|url| url.starts_with("https://") || url.starts_with("http://"));
+        if let Some(artwork_url) = artwork_url {
+            activity["assets"] = json!({ "large_image": artwork_url });
+            if let Some(album) = &song.album {
+                activity["assets"]["large_text"] = json!(field(album));
+            }
+        }
+        activity
+    }
+}
+
+/// Fits text into an activity field, which must be between 2 and 128 characters long.
+fn field(text: &str) -> String {
+    let mut text = text.chars().take(128).collect::<String>();
+    while text.chars().count() < 2 {
+        text.push(' ');
+    }
+    text
+}
+
+impl Actor for DiscordActor {
+    type MessageType = Option<SongInfo>;
+    fn spawn(self) -> ActorHandle<Self::MessageType> {
+        let (sender, receiver) = flume::unbounded::<Option<SongInfo>>();
+        ActorHandle {
+            sender,
+            thread_handle: thread::spawn(move || {
+                let mut connection: Option<Connection> = None;
+                let mut activity: Option<Value> = None;
+
+                loop {
+                    match receiver.recv_timeout(REFRESH_INTERVAL) {
+                        Ok(song) => {
+                            activity = song.map(|song| self.activity(&song, unix_time()));
+                        }
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+
+                    if connection.is_none() {
+                        let discord_config = self.config.discord().clone();
+                        connection = with_timeout(move || Connection::open(&discord_config)).ok();
+                    }
+                    if let Some(mut conn) = connection.take() {
+                        let activity = activity.clone();
+                        match with_timeout(move || {
+                            conn.set_activity(activity.as_ref()).map(|_| conn)
+                        }) {
+                            Ok(conn) => connection = Some(conn),
+                            Err(err) => warn!("  | Lost connection to Discord: {err}"),
+                        }
+                    }
+                }
+
+                if let Some(mut conn) = connection {
+                    let _ = with_timeout(move || conn.set_activity(None));
+                }
+            }),
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    io::Read,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Error};
+use serde::Deserialize;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{config::BrowserBridgeConfig, http, song::SongInfo};
+
+use super::{Driver, PlayerCommand};
+
+/// Largest request body the bridge is willing to read.
+const MAX_BODY_SIZE: u64 = 64 * 1024;
+
+/// A [Driver] that receives song information from a companion
+/// browser extension or userscript, which reports what is playing
+/// in YouTube, SoundCloud or Bandcamp tabs via `POST /ingest`.
+///
+/// Player commands are queued and handed to the browser
+/// in the response to its next report as `{"commands": [...]}`.
+pub struct BrowserBridgeDriver {
+    server: Option<Arc<Server>>,
+    last_report: Arc<Mutex<Option<Report>>>,
+    pending_commands: Arc<Mutex<Vec<PlayerCommand>>>,
+    timeout: Duration,
+}
+
+struct Report {
+    song: SongInfo,
+    received_at: Instant,
+}
+
+/// Body of an ingest request.
+/// A report with `playing` set to false clears the current song.
+#[derive(Deserialize)]
+struct IngestBody {
+    #[serde(default = "default_playing")]
+    playing: bool,
+    #[serde(default)]
+    artist: String,
+    #[serde(default)]
+    title: String,
+    album: Option<String>,
+    artwork_url: Option<String>,
+    /// Length of the song in seconds.
+    duration: Option<f64>,
+}
+
+fn default_playing() -> bool {
+    true
+}
+
+impl BrowserBridgeDriver {
+    pub fn new(config: &BrowserBridgeConfig) -> BrowserBridgeDriver {
+        let last_report = Arc::new(Mutex::new(None));
+        let pending_commands = Arc::new(Mutex::new(Vec::new()));
+        if config.token.is_empty() {
+            warn!("  | browser-bridge: no token configured, all reports will be rejected");
+        }
+
+        let server = match http::listen(config.port) {
+            Ok(server) => {
+                let server = Arc::new(server);
+                let thread_server = server.clone();
+                let thread_report = last_report.clone();
+                let thread_commands = pending_commands.clone();
+                let token = config.token.clone();
+                thread::spawn(move || {
+                    while let Ok(request) = thread_server.recv() {
+                        handle_request(request, &token, &thread_report, &thread_commands);
+                    }
+                });
+                Some(server)
+            }
+            Err(err) => {
+                warn!(
+                    "  | browser-bridge: cannot listen on port {}: {err:?}",
+                    config.port
+                );
+                None
+            }
+        };
+
+        BrowserBridgeDriver {
+            server,
+            last_report,
+            pending_commands,
+            timeout: Duration::from_secs(config.timeout_secs),
+        }
+    }
+}
+
+impl Driver for BrowserBridgeDriver {
+    fn fetch_song_info(&mut self) -> Option<SongInfo> {
+        let mut last_report = self.last_report.lock().unwrap();
+        match last_report.as_ref() {
+            Some(report) if report.received_at.elapsed() <= self.timeout => {
+                Some(report.song.clone())
+            }
+            Some(_) => {
+                // The tab was probably closed without telling us
+                *last_report = None;
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn control(&mut self, command: &PlayerCommand) -> Result<(), Error> {
+        if self.fetch_song_info().is_none() && *command != PlayerCommand::Play {
+            return Err(anyhow!("no browser tab is reporting"));
+        }
+        self.pending_commands.lock().unwrap().push(command.clone());
+        Ok(())
+    }
+}
+
+impl Drop for BrowserBridgeDriver {
+    fn drop(&mut self) {
+        if let Some(server) = &self.server {
+            server.unblock();
+        }
+    }
+}
+
+fn handle_request(
+    mut request: Request,
+    token: &str,
+    last_report: &Mutex<Option<Report>>,
+    pending_commands: &Mutex<Vec<PlayerCommand>>,
+) {
+    let mut body = None;
+    let status = if request.url() != "/ingest" {
+        404
+    } else if *request.method() == Method::Options {
+        // CORS preflight from an extension's content script
+        204
+    } else if *request.method() != Method::Post {
+        405
+    } else if token.is_empty() || !has_token(&request, token) {
+        401
+    } else {
+        let mut ingest = String::new();
+        let read = request
+            .as_reader()
+            .take(MAX_BODY_SIZE)
+            .read_to_string(&mut ingest);
+        match read
+            .ok()
+            .and_then(|_| serde_json::from_str::<IngestBody>(&ingest).ok())
+        {
+            Some(ingest) => {
+                let mut last_report = last_report.lock().unwrap();
+                *last_report = if ingest.playing && !ingest.title.is_empty() {
+                    Some(Report {
+                        song: SongInfo {
+                            artist: ingest.artist,
+                            title: ingest.title,
+                            album: ingest.album,
+                            artwork_url: ingest.artwork_url,
+                            path: None,
+                            duration: ingest.duration.filter(|d| d.is_finite() && *d > 0.0),
+                            custom: false,
+                        },
+                        received_at: Instant::now(),
+                    })
+                } else {
+                    None
+                };
+
+                let commands = std::mem::take(&mut *pending_commands.lock().unwrap());
+                if commands.is_empty() {
+                    204
+                } else {
+                    body = serde_json::to_string(&serde_json::json!({ "commands": commands })).ok();
+                    200
+                }
+            }
+            None => 400,
+        }
+    };
+
+    let response = Response::from_string(body.unwrap_or_default())
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json"))
+        .with_header(header("Access-Control-Allow-Origin", "*"))
+        .with_header(header(
+            "Access-Control-Allow-Headers",
+            "Content-Type, X-CurrentSong-Token",
+        ));
+    let _ = request.respond(response);
+}
+
+/// Checks whether the request carries the expected shared secret.
+fn has_token(request: &Request, token: &str) -> bool {
+    http::header(request.headers(), "X-CurrentSong-Token")
+        .is_some_and(|value| http::constant_time_eq(value.as_bytes(), token.as_bytes()))
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field.as_bytes(), value.as_bytes()).unwrap()
+}
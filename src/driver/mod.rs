@@ -1,9 +1,30 @@
-use crate::song::SongInfo;
+use std::{fs, path::Path, str::FromStr};
 
+use anyhow::{anyhow, Error};
+use serde::Serialize;
+
+use crate::{config::Config, song::SongInfo};
+
+use self::{
+    browser_bridge::BrowserBridgeDriver, mpd::MpdDriver, script::ScriptDriver,
+    spotify_web::SpotifyWebDriver,
+};
+
+#[cfg(target_os = "linux")]
+use self::mpris::MprisDriver;
+
+#[cfg(target_os = "windows")]
 use self::spotify_desktop::SpotifyDesktopDriver;
 
+mod browser_bridge;
+mod mpd;
+#[cfg(target_os = "linux")]
+mod mpris;
 mod noop;
+mod script;
+#[cfg(target_os = "windows")]
 mod spotify_desktop;
+mod spotify_web;
 
 pub use noop::noop;
 
@@ -11,12 +32,120 @@ pub use noop::noop;
 pub trait Driver {
     /// Get currently playing song's info, if it exists.
     fn fetch_song_info(&mut self) -> Option<SongInfo>;
+
+    /// Asks the media player to execute a playback command.
+    /// Drivers that cannot control their player keep the default implementation.
+    fn control(&mut self, _command: &PlayerCommand) -> Result<(), Error> {
+        Err(anyhow!("this driver cannot control the player"))
+    }
+}
+
+/// A command controlling playback of a media player.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum PlayerCommand {
+    Play,
+    Pause,
+    Toggle,
+    Next,
+    Previous,
+    /// Seek to an absolute position in the current song.
+    Seek {
+        seconds: f64,
+    },
+    /// Set the volume, from 0.0 to 1.0.
+    Volume {
+        level: f64,
+    },
+}
+
+impl FromStr for PlayerCommand {
+    type Err = Error;
+
+    /// Parses commands such as `next`, `seek 90` or `volume 0.5`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let name = parts.next().unwrap_or_default().to_lowercase();
+        let mut argument = || -> Result<f64, Error> {
+            let argument = parts
+                .next()
+                .ok_or_else(|| anyhow!("{name} requires an argument"))?;
+            Ok(argument.parse()?)
+        };
+        let command = match name.as_str() {
+            "play" => PlayerCommand::Play,
+            "pause" => PlayerCommand::Pause,
+            "toggle" | "play-pause" => PlayerCommand::Toggle,
+            "next" | "skip" => PlayerCommand::Next,
+            "previous" | "prev" => PlayerCommand::Previous,
+            "seek" => PlayerCommand::Seek {
+                seconds: argument()?.max(0.0),
+            },
+            "volume" => PlayerCommand::Volume {
+                level: argument()?.clamp(0.0, 1.0),
+            },
+            _ => return Err(anyhow!("unknown player command: \"{s}\"")),
+        };
+        Ok(command)
+    }
 }
 
 /// Factory for creating Driver implementations based on their names.
-pub fn create(name: &str) -> Option<Box<dyn Driver>> {
+/// Names not matching a built-in driver are looked up
+/// as `drivers/<name>.rhai` scripts in the data directory.
+pub fn create(name: &str, config: &Config, data_directory: &Path) -> Option<Box<dyn Driver>> {
     match name {
+        #[cfg(target_os = "windows")]
         "spotify-desktop" => Some(Box::new(SpotifyDesktopDriver::new())),
-        _ => None,
+        "browser-bridge" => Some(Box::new(BrowserBridgeDriver::new(config.browser_bridge()))),
+        "mpd" => Some(Box::new(MpdDriver::new(config.mpd()))),
+        #[cfg(target_os = "linux")]
+        "mpris" => Some(Box::new(MprisDriver::new(config.mpris()))),
+        "spotify-web" => Some(Box::new(SpotifyWebDriver::new(config.spotify_web()))),
+        _ => create_script(name, data_directory),
+    }
+}
+
+/// Returns the names of the drivers built into the app on this platform.
+pub fn built_in_names() -> Vec<&'static str> {
+    let mut names = vec!["browser-bridge", "mpd", "spotify-web"];
+    if cfg!(target_os = "windows") {
+        names.insert(0, "spotify-desktop");
+    }
+    if cfg!(target_os = "linux") {
+        names.push("mpris");
+    }
+    names
+}
+
+/// Returns the names of the driver scripts in the data directory, sorted.
+pub fn script_names(data_directory: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(data_directory.join("drivers")) else {
+        return Vec::new();
+    };
+    let mut names = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "rhai" {
+                return None;
+            }
+            Some(path.file_stem()?.to_string_lossy().into_owned())
+        })
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+fn create_script(name: &str, data_directory: &Path) -> Option<Box<dyn Driver>> {
+    let path = data_directory.join("drivers").join(format!("{name}.rhai"));
+    if !path.is_file() {
+        return None;
+    }
+    match ScriptDriver::load(&path) {
+        Ok(driver) => Some(Box::new(driver)),
+        Err(err) => {
+            warn!("  | Cannot load driver script {:?}: {}", &path, err);
+            Some(noop())
+        }
     }
 }
@@ -0,0 +1,216 @@
+use std::{
+    fmt,
+    io::{BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Error};
+
+use crate::{config::MpdConfig, song::SongInfo};
+
+use super::{Driver, PlayerCommand};
+
+/// How long to wait before connecting again after the connection failed.
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// A [Driver] that asks a Music Player Daemon for the current song over its text protocol.
+pub struct MpdDriver {
+    config: MpdConfig,
+    connection: Option<Connection>,
+    /// When to try connecting again after a failure.
+    retry_at: Instant,
+}
+
+/// Response to a command, as `key: value` pairs in the order they were sent.
+type Response = Vec<(String, String)>;
+
+/// MPD refused a command, e.g. because there is no song to skip to.
+/// Unlike other errors, this does not mean the connection is lost.
+#[derive(Debug)]
+struct CommandFailed(String);
+
+impl fmt::Display for CommandFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for CommandFailed {}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Connection {
+    fn open(config: &MpdConfig) -> Result<Connection, Error> {
+        let timeout = Duration::from_secs(config.timeout_secs);
+        let address = (config.host.as_str(), config.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("cannot resolve {}", config.host))?;
+        let stream = TcpStream::connect_timeout(&address, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        let mut connection = Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        };
+        let greeting = connection.read_line()?;
+        if !greeting.starts_with("OK MPD ") {
+            return Err(anyhow!("unexpected greeting: {greeting}"));
+        }
+        if let Some(password) = &config.password {
+            connection.command(&format!("password {}", quote(password)))?;
+        }
+        Ok(connection)
+    }
+
+    fn read_line(&mut self) -> Result<String, Error> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(anyhow!("connection closed"));
+        }
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    /// Sends a command and reads the response up to the closing `OK`.
+    fn command(&mut self, command: &str) -> Result<Response, Error> {
+        writeln!(self.writer, "{command}")?;
+        let mut response = Vec::new();
+        loop {
+            let line = self.read_line()?;
+            if line == "OK" {
+                return Ok(response);
+            }
+            if let Some(error) = line.strip_prefix("ACK ") {
+                return Err(CommandFailed(error.to_string()).into());
+            }
+            if let Some((key, value)) = line.split_once(": ") {
+                response.push((key.to_string(), value.to_string()));
+            }
+        }
+    }
+}
+
+/// Quotes an argument of a command.
+fn quote(argument: &str) -> String {
+    format!(
+        "\"{}\"",
+        argument.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+fn field<'a>(response: &'a Response, key: &str) -> Option<&'a str> {
+    response
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, value)| value.as_str())
+}
+
+impl MpdDriver {
+    pub fn new(config: &MpdConfig) -> MpdDriver {
+        MpdDriver {
+            config: config.clone(),
+            connection: None,
+            retry_at: Instant::now(),
+        }
+    }
+
+    /// Sends a command, connecting first if needed.
+    /// The connection is dropped on errors other than the server refusing the command.
+    fn command(&mut self, command: &str) -> Result<Response, Error> {
+        if self.connection.is_none() {
+            if Instant::now() < self.retry_at {
+                return Err(anyhow!("not connected to MPD"));
+            }
+            match Connection::open(&self.config) {
+                Ok(connection) => self.connection = Some(connection),
+                Err(err) => {
+                    self.retry_at = Instant::now() + RECONNECT_DELAY;
+                    return Err(anyhow!("cannot connect to MPD: {err}"));
+                }
+            }
+        }
+        let connection = self.connection.as_mut().unwrap();
+        let result = connection.command(command);
+        if result.as_ref().is_err_and(|err| !err.is::<CommandFailed>()) {
+            self.connection = None;
+        }
+        result
+    }
+
+    fn state(&mut self) -> Result<String, Error> {
+        let status = self.command("status")?;
+        Ok(field(&status, "state").unwrap_or("stop").to_string())
+    }
+
+    fn current_song(&mut self) -> Result<Option<SongInfo>, Error> {
+        let status = self.command("status")?;
+        if field(&status, "state") != Some("play") {
+            return Ok(None);
+        }
+        let song = self.command("currentsong")?;
+        let file = field(&song, "file").unwrap_or_default();
+        // Streams and files without tags are known by their name only
+        let title = field(&song, "Title")
+            .or_else(|| field(&song, "Name"))
+            .unwrap_or_else(|| file.rsplit('/').next().unwrap_or(file));
+        let duration = field(&status, "duration")
+            .or_else(|| field(&song, "duration"))
+            .and_then(|d| d.parse::<f64>().ok())
+            .filter(|d| d.is_finite() && *d > 0.0);
+        let path = self
+            .config
+            .music_directory
+            .as_ref()
+            .filter(|_| !file.is_empty() && !file.contains("://"))
+            .map(|directory| directory.join(file));
+        Ok(Some(SongInfo {
+            artist: field(&song, "Artist").unwrap_or_default().to_string(),
+            title: title.to_string(),
+            album: field(&song, "Album").map(str::to_string),
+            artwork_url: None,
+            path,
+            duration,
+            custom: false,
+        }))
+    }
+}
+
+impl Driver for MpdDriver {
+    fn fetch_song_info(&mut self) -> Option<SongInfo> {
+        if self.connection.is_none() && Instant::now() < self.retry_at {
+            return None;
+        }
+        match self.current_song() {
+            Ok(song) => song,
+            Err(err) => {
+                warn!("  | mpd: {err}");
+                None
+            }
+        }
+    }
+
+    fn control(&mut self, command: &PlayerCommand) -> Result<(), Error> {
+        let line = match command {
+            PlayerCommand::Play => match self.state()?.as_str() {
+                "pause" => "pause 0".to_string(),
+                _ => "play".to_string(),
+            },
+            PlayerCommand::Pause => "pause 1".to_string(),
+            PlayerCommand::Toggle => match self.state()?.as_str() {
+                "play" => "pause 1".to_string(),
+                "pause" => "pause 0".to_string(),
+                _ => "play".to_string(),
+            },
+            PlayerCommand::Next => "next".to_string(),
+            PlayerCommand::Previous => "previous".to_string(),
+            PlayerCommand::Seek { seconds } => format!("seekcur {seconds}"),
+            PlayerCommand::Volume { level } => format!("setvol {}", (level * 100.0).round()),
+        };
+        self.command(&line).map(|_| ())
+    }
+}
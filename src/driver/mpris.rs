@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Error};
+use url::Url;
+use zbus::{
+    blocking::{proxy::Builder, Connection, Proxy},
+    proxy::CacheProperties,
+    zvariant::{ObjectPath, OwnedValue, Value},
+};
+
+use crate::{config::MprisConfig, song::SongInfo};
+
+use super::{Driver, PlayerCommand};
+
+const BUS_NAME_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+/// A [Driver] that follows media players through the MPRIS interface on the session bus,
+/// which most Linux players and browsers provide.
+pub struct MprisDriver {
+    config: MprisConfig,
+    connection: Option<Connection>,
+    /// Bus name of the player that was playing last, which player commands go to.
+    player: Option<String>,
+    /// ID of the song reported last, needed for seeking.
+    track_id: Option<String>,
+}
+
+type Metadata = HashMap<String, OwnedValue>;
+
+impl MprisDriver {
+    pub fn new(config: &MprisConfig) -> MprisDriver {
+        MprisDriver {
+            config: config.clone(),
+            connection: None,
+            player: None,
+            track_id: None,
+        }
+    }
+
+    fn connection(&mut self) -> Result<&Connection, Error> {
+        if self.connection.is_none() {
+            self.connection = Some(Connection::session()?);
+        }
+        Ok(self.connection.as_ref().unwrap())
+    }
+
+    /// Returns the bus names of the players on the bus, narrowed down to the configured one.
+    fn players(&mut self) -> Result<Vec<String>, Error> {
+        let filter = self.config.player.as_ref().map(|p| p.to_lowercase());
+        let reply = self.connection()?.call_method(
+            Some("org.freedesktop.DBus"),
+            "/org/freedesktop/DBus",
+            Some("org.freedesktop.DBus"),
+            "ListNames",
+            &(),
+        )?;
+        let names = reply.body().deserialize::<Vec<String>>()?;
+        Ok(names
+            .into_iter()
+            .filter(|name| {
+                name.strip_prefix(BUS_NAME_PREFIX).is_some_and(|player| {
+                    filter
+                        .as_ref()
+                        .is_none_or(|filter| player.to_lowercase().contains(filter))
+                })
+            })
+            .collect())
+    }
+
+    fn proxy(&mut self, name: &str) -> Result<Proxy<'static>, Error> {
+        let connection = self.connection()?;
+        // Players change their properties all the time, so always ask for the current values
+        Ok(Builder::new(connection)
+            .destination(name.to_string())?
+            .path(OBJECT_PATH)?
+            .interface(PLAYER_INTERFACE)?
+            .cache_properties(CacheProperties::No)
+            .build()?)
+    }
+
+    /// Finds a player that is playing, preferring the one that was playing last.
+    fn playing(&mut self) -> Result<Option<(String, Metadata)>, Error> {
+        let mut players = self.players()?;
+        if let Some(last) = &self.player {
+            if let Some(index) = players.iter().position(|name| name == last) {
+                let last = players.remove(index);
+                players.insert(0, last);
+            }
+        }
+        for name in players {
+            let proxy = self.proxy(&name)?;
+            // A player may go away or misbehave, that should not hide the others
+            let Ok(status) = proxy.get_property::<String>("PlaybackStatus") else {
+                continue;
+            };
+            if status == "Playing" {
+                let metadata = proxy.get_property::<Metadata>("Metadata")?;
+                return Ok(Some((name, metadata)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the player to send commands to: the one playing last, or any player.
+    fn target(&mut self) -> Result<Proxy<'static>, Error> {
+        let name = match &self.player {
+            Some(name) => name.clone(),
+            None => self
+                .players()?
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("no MPRIS player is running"))?,
+        };
+        self.proxy(&name)
+    }
+}
+
+/// Reads a text field, joining lists such as `xesam:artist`.
+fn text(metadata: &Metadata, key: &str) -> Option<String> {
+    let text = match &**metadata.get(key)? {
+        Value::Str(s) => s.to_string(),
+        Value::ObjectPath(path) => path.to_string(),
+        Value::Array(array) => array
+            .iter()
+            .filter_map(|value| match value {
+                Value::Str(s) => Some(s.to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => return None,
+    };
+    Some(text).filter(|text| !text.is_empty())
+}
+
+/// Reads `mpris:length`, which players send as different integer types, in seconds.
+fn length(metadata: &Metadata) -> Option<f64> {
+    let microseconds = match &**metadata.get("mpris:length")? {
+        Value::I64(n) => *n as f64,
+        Value::U64(n) => *n as f64,
+        Value::I32(n) => *n as f64,
+        Value::U32(n) => *n as f64,
+        Value::F64(n) => *n,
+        _ => return None,
+    };
+    Some(microseconds / 1_000_000.0).filter(|d| d.is_finite() && *d > 0.0)
+}
+
+fn song_from_metadata(metadata: &Metadata) -> Option<SongInfo> {
+    let title = text(metadata, "xesam:title")?;
+    let artwork_url = text(metadata, "mpris:artUrl");
+    let path = text(metadata, "xesam:url")
+        .and_then(|url| Url::parse(&url).ok())
+        .filter(|url| url.scheme() == "file")
+        .and_then(|url| url.to_file_path().ok());
+    Some(SongInfo {
+        artist: text(metadata, "xesam:artist").unwrap_or_default(),
+        title,
+        album: text(metadata, "xesam:album"),
+        artwork_url,
+        path,
+        duration: length(metadata),
+        custom: false,
+    })
+}
+
+impl Driver for MprisDriver {
+    fn fetch_song_info(&mut self) -> Option<SongInfo> {
+        match self.playing() {
+            Ok(Some((name, metadata))) => {
+                self.player = Some(name);
+                self.track_id = text(&metadata, "mpris:trackid");
+                song_from_metadata(&metadata)
+            }
+            Ok(None) => None,
+            Err(err) => {
+                warn!("  | mpris: {err}");
+                // The bus may have gone away, connect again next time
+                self.connection = None;
+                None
+            }
+        }
+    }
+
+    fn control(&mut self, command: &PlayerCommand) -> Result<(), Error> {
+        let proxy = self.target()?;
+        match command {
+            PlayerCommand::Play => proxy.call_method("Play", &())?,
+            PlayerCommand::Pause => proxy.call_method("Pause", &())?,
+            PlayerCommand::Toggle => proxy.call_method("PlayPause", &())?,
+            PlayerCommand::Next => proxy.call_method("Next", &())?,
+            PlayerCommand::Previous => proxy.call_method("Previous", &())?,
+            PlayerCommand::Seek { seconds } => {
+                let track_id = self
+                    .track_id
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("the player has not reported a song to seek in"))?;
+                let position = (seconds * 1_000_000.0) as i64;
+                proxy.call_method("SetPosition", &(ObjectPath::try_from(track_id)?, position))?
+            }
+            PlayerCommand::Volume { level } => {
+                proxy.set_property("Volume", *level)?;
+                return Ok(());
+            }
+        };
+        Ok(())
+    }
+}
@@ -0,0 +1,252 @@
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Error};
+use flume::Receiver;
+use regex::Regex;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+
+use crate::{http, process::wait_timeout, song::SongInfo};
+
+use super::{Driver, PlayerCommand};
+
+/// Upper bound of operations a single `fetch` call may perform,
+/// so that a runaway script cannot stall the application.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Longest a command started by a script may run.
+/// Scripts are called from the main loop, which waits for them.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for the rest of a command's output after it has exited.
+/// A process it started in the background may keep the pipes open.
+const OUTPUT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A [Driver] defined by a user script in the `drivers` directory.
+///
+/// The script must define a `fetch()` function returning either `()`
+/// when nothing is playing, or a map with `artist` and `title` keys,
+/// and optionally `album`, `artwork_url`, `path` and `duration` (in seconds).
+/// It may also define `control(command)`, which receives a map such as
+/// `#{command: "seek", seconds: 90.0}` to control the player.
+pub struct ScriptDriver {
+    name: String,
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+}
+
+type HostResult<T> = Result<T, Box<EvalAltResult>>;
+
+impl ScriptDriver {
+    /// Compiles the script at the provided path.
+    pub fn load(path: &Path) -> Result<ScriptDriver, String> {
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let engine = create_engine();
+        let source = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let ast = engine.compile(source).map_err(|err| err.to_string())?;
+
+        let mut scope = Scope::new();
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|err| err.to_string())?;
+
+        Ok(ScriptDriver {
+            name,
+            engine,
+            ast,
+            scope,
+        })
+    }
+}
+
+impl Driver for ScriptDriver {
+    fn fetch_song_info(&mut self) -> Option<SongInfo> {
+        let result = self
+            .engine
+            .call_fn::<Dynamic>(&mut self.scope, &self.ast, "fetch", ());
+        match result {
+            Ok(value) if value.is_unit() => None,
+            Ok(value) => match value.try_cast::<Map>() {
+                Some(map) => song_from_map(&map),
+                None => {
+                    warn!("  | {}.rhai: fetch() must return a map or ()", self.name);
+                    None
+                }
+            },
+            Err(err) => {
+                warn!("  | {}.rhai: {}", self.name, err);
+                None
+            }
+        }
+    }
+
+    fn control(&mut self, command: &PlayerCommand) -> Result<(), Error> {
+        let mut map = Map::new();
+        let name = match command {
+            PlayerCommand::Play => "play",
+            PlayerCommand::Pause => "pause",
+            PlayerCommand::Toggle => "toggle",
+            PlayerCommand::Next => "next",
+            PlayerCommand::Previous => "previous",
+            PlayerCommand::Seek { seconds } => {
+                map.insert("seconds".into(), (*seconds).into());
+                "seek"
+            }
+            PlayerCommand::Volume { level } => {
+                map.insert("level".into(), (*level).into());
+                "volume"
+            }
+        };
+        map.insert("command".into(), name.into());
+        self.engine
+            .call_fn::<Dynamic>(&mut self.scope, &self.ast, "control", (map,))
+            .map(|_| ())
+            .map_err(|err| anyhow!("{}.rhai: {}", self.name, err))
+    }
+}
+
+fn song_from_map(map: &Map) -> Option<SongInfo> {
+    let field = |key: &str| {
+        map.get(key)
+            .filter(|v| !v.is_unit())
+            .map(|v| v.to_string())
+            .unwrap_or_default()
+    };
+    let title = field("title");
+    if title.is_empty() {
+        return None;
+    }
+    let optional = |key: &str| Some(field(key)).filter(|v| !v.is_empty());
+    Some(SongInfo {
+        artist: field("artist"),
+        title,
+        album: optional("album"),
+        artwork_url: optional("artwork_url"),
+        path: optional("path").map(PathBuf::from),
+        duration: map
+            .get("duration")
+            .and_then(|v| {
+                v.as_float()
+                    .ok()
+                    .or_else(|| v.as_int().ok().map(|i| i as f64))
+            })
+            .filter(|d| d.is_finite() && *d > 0.0),
+        custom: false,
+    })
+}
+
+/// Creates a scripting engine with host functions available to driver scripts.
+fn create_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.register_fn("http_get", http_get);
+    engine.register_fn("read_file", read_file);
+    engine.register_fn("run_command", |program: &str| {
+        run_command(program, Array::new())
+    });
+    engine.register_fn("run_command", run_command);
+    engine.register_fn("regex_match", regex_match);
+    engine.register_fn("regex_captures", regex_captures);
+    engine
+}
+
+/// Performs a GET request against a local web server and returns the response body.
+/// Redirects are not followed, as they could lead anywhere.
+fn http_get(url: &str) -> HostResult<String> {
+    let agent = http::agent_builder(Duration::from_secs(2))
+        .redirects(0)
+        .build();
+    let request = agent.get(url);
+    let host = request
+        .request_url()
+        .map_err(|err| err.to_string())?
+        .host()
+        .to_owned();
+    if !matches!(host.as_str(), "localhost" | "127.0.0.1" | "::1" | "[::1]") {
+        return Err(format!("http_get is limited to localhost, got {host}").into());
+    }
+    let response = request.call().map_err(|err| err.to_string())?;
+    if (300..400).contains(&response.status()) {
+        return Err(format!(
+            "http_get does not follow redirects, got {}",
+            response.status()
+        )
+        .into());
+    }
+    Ok(response.into_string().map_err(|err| err.to_string())?)
+}
+
+fn read_file(path: &str) -> HostResult<String> {
+    Ok(fs::read_to_string(path).map_err(|err| format!("cannot read {path}: {err}"))?)
+}
+
+/// Runs a program to completion and returns a map
+/// with its exit `code`, `stdout` and `stderr`.
+/// The program is killed if it does not exit in time.
+fn run_command(program: &str, args: Array) -> HostResult<Map> {
+    let mut child = Command::new(program)
+        .args(args.iter().map(|a| a.to_string()))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("cannot run {program}: {err}"))?;
+
+    // Read the output while waiting, so that a full pipe does not block the program
+    let stdout = read_to_end(child.stdout.take());
+    let stderr = read_to_end(child.stderr.take());
+    let status =
+        wait_timeout(&mut child, COMMAND_TIMEOUT).map_err(|err| format!("{program}: {err}"))?;
+    let output = |receiver: Receiver<Vec<u8>>| {
+        let output = receiver.recv_timeout(OUTPUT_TIMEOUT).unwrap_or_default();
+        String::from_utf8_lossy(&output).into_owned()
+    };
+
+    let mut map = Map::new();
+    map.insert("code".into(), (status.code().unwrap_or(-1) as i64).into());
+    map.insert("stdout".into(), output(stdout).into());
+    map.insert("stderr".into(), output(stderr).into());
+    Ok(map)
+}
+
+/// Reads a pipe until it is closed on another thread.
+fn read_to_end<R: Read + Send + 'static>(pipe: Option<R>) -> Receiver<Vec<u8>> {
+    let (sender, receiver) = flume::bounded(1);
+    if let Some(mut pipe) = pipe {
+        thread::spawn(move || {
+            let mut output = Vec::new();
+            let _ = pipe.read_to_end(&mut output);
+            let _ = sender.send(output);
+        });
+    }
+    receiver
+}
+
+fn regex_match(pattern: &str, text: &str) -> HostResult<bool> {
+    let regex = Regex::new(pattern).map_err(|err| err.to_string())?;
+    Ok(regex.is_match(text))
+}
+
+/// Returns capture groups of the first match (group 0 being the whole match),
+/// or `()` if the pattern does not match.
+fn regex_captures(pattern: &str, text: &str) -> HostResult<Dynamic> {
+    let regex = Regex::new(pattern).map_err(|err| err.to_string())?;
+    let Some(captures) = regex.captures(text) else {
+        return Ok(Dynamic::UNIT);
+    };
+    let groups = captures
+        .iter()
+        .map(|m| m.map(|m| m.as_str().to_owned()).unwrap_or_default().into())
+        .collect::<Array>();
+    Ok(groups.into())
+}
@@ -1,8 +1,9 @@
-use crate::process;
+use crate::process::{self, MediaKey};
 use crate::song::SongInfo;
+use anyhow::{anyhow, Error};
 use sysinfo::{PidExt, ProcessExt, ProcessRefreshKind, System, SystemExt};
 
-use super::Driver;
+use super::{Driver, PlayerCommand};
 
 /// A [Driver] that fetches song information
 /// from a locally installed Spotify app (free or premium).
@@ -30,9 +31,32 @@ impl Driver for SpotifyDesktopDriver {
                 return Some(SongInfo {
                     artist: artist.into(),
                     title: title.into(),
+                    ..Default::default()
                 });
             }
         }
         None
     }
+
+    /// Controls playback with media keys, as Spotify offers no local API.
+    /// Spotify only shows the song in its window title while playing,
+    /// which tells whether play and pause need to toggle anything.
+    fn control(&mut self, command: &PlayerCommand) -> Result<(), Error> {
+        let key = match command {
+            PlayerCommand::Toggle => MediaKey::PlayPause,
+            PlayerCommand::Play if self.fetch_song_info().is_none() => MediaKey::PlayPause,
+            PlayerCommand::Pause if self.fetch_song_info().is_some() => MediaKey::PlayPause,
+            PlayerCommand::Play | PlayerCommand::Pause => return Ok(()),
+            PlayerCommand::Next => MediaKey::NextTrack,
+            PlayerCommand::Previous => MediaKey::PreviousTrack,
+            PlayerCommand::Seek { .. } | PlayerCommand::Volume { .. } => {
+                return Err(anyhow!("the Spotify desktop app cannot seek or set volume"));
+            }
+        };
+        if process::press_media_key(key) {
+            Ok(())
+        } else {
+            Err(anyhow!("cannot send media key"))
+        }
+    }
 }
@@ -0,0 +1,201 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Error};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde_json::Value;
+use ureq::{Agent, Request};
+
+use crate::{config::SpotifyWebConfig, http, song::SongInfo};
+
+use super::{Driver, PlayerCommand};
+
+const TOKEN_URL: &str = "https://accounts.spotify.com/api/token";
+const API_URL: &str = "https://api.spotify.com/v1/me/player";
+
+/// Access tokens are renewed this long before they expire.
+const TOKEN_MARGIN: Duration = Duration::from_secs(60);
+
+/// How long to wait after a failure when Spotify does not say how long to wait.
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// A [Driver] that asks the Spotify Web API what the user is playing on any of their devices.
+pub struct SpotifyWebDriver {
+    agent: Agent,
+    config: SpotifyWebConfig,
+    access_token: Option<AccessToken>,
+    /// When to ask again after a failure or being rate limited.
+    retry_at: Instant,
+    /// Whether the player was playing when asked last, to know what toggling means.
+    playing: bool,
+    /// The song found last, reported while waiting to ask again.
+    song: Option<SongInfo>,
+}
+
+struct AccessToken {
+    token: String,
+    expires_at: Instant,
+}
+
+impl SpotifyWebDriver {
+    pub fn new(config: &SpotifyWebConfig) -> SpotifyWebDriver {
+        if config.refresh_token.is_empty() {
+            warn!("  | spotify-web: no refresh token configured, no songs will be found");
+        }
+        SpotifyWebDriver {
+            agent: http::agent(Duration::from_secs(config.timeout_secs)),
+            config: config.clone(),
+            access_token: None,
+            retry_at: Instant::now(),
+            playing: false,
+            song: None,
+        }
+    }
+
+    /// Returns an access token, exchanging the refresh token for a new one if needed.
+    fn access_token(&mut self) -> Result<String, Error> {
+        if let Some(token) = &self.access_token {
+            if Instant::now() + TOKEN_MARGIN < token.expires_at {
+                return Ok(token.token.clone());
+            }
+        }
+        let credentials = BASE64.encode(format!(
+            "{}:{}",
+            self.config.client_id, self.config.client_secret
+        ));
+        let response: Value = self
+            .agent
+            .post(TOKEN_URL)
+            .set("Authorization", &format!("Basic {credentials}"))
+            .send_form(&[
+                ("grant_type", "refresh_token"),
+                ("refresh_token", &self.config.refresh_token),
+            ])
+            .map_err(|err| anyhow!("cannot get an access token: {err}"))
+            .and_then(|response| Ok(serde_json::from_reader(response.into_reader())?))?;
+        let token = response["access_token"]
+            .as_str()
+            .ok_or_else(|| anyhow!("no access token in the response"))?
+            .to_string();
+        // Spotify may rotate the refresh token, the old one keeps working until then
+        if let Some(refresh_token) = response["refresh_token"].as_str() {
+            self.config.refresh_token = refresh_token.to_string();
+        }
+        let expires_in = response["expires_in"].as_u64().unwrap_or(3600);
+        self.access_token = Some(AccessToken {
+            token: token.clone(),
+            expires_at: Instant::now() + Duration::from_secs(expires_in),
+        });
+        Ok(token)
+    }
+
+    /// Sends an API request, returning the response body if there is one.
+    fn send(&mut self, request: Request) -> Result<Option<Value>, Error> {
+        let token = self.access_token()?;
+        let response = request
+            .set("Authorization", &format!("Bearer {token}"))
+            .set("Content-Length", "0")
+            .call();
+        match response {
+            Ok(response) if response.status() == 204 => Ok(None),
+            Ok(response) => {
+                let body = response.into_string()?;
+                Ok(serde_json::from_str(&body).ok())
+            }
+            Err(ureq::Error::Status(status, response)) => {
+                if status == 401 {
+                    // The token may have been revoked before it expired
+                    self.access_token = None;
+                }
+                let retry_after = response
+                    .header("Retry-After")
+                    .and_then(|secs| secs.parse().ok())
+                    .map(Duration::from_secs);
+                if status == 429 || retry_after.is_some() {
+                    self.retry_at = Instant::now() + retry_after.unwrap_or(RETRY_DELAY);
+                }
+                let body: Value =
+                    serde_json::from_reader(response.into_reader()).unwrap_or_default();
+                let message = body["error"]["message"].as_str().unwrap_or("unknown error");
+                Err(anyhow!("request failed with status {status}: {message}"))
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn current_song(&mut self) -> Result<Option<SongInfo>, Error> {
+        let request = self.agent.get(&format!("{API_URL}/currently-playing"));
+        let Some(playing) = self.send(request)? else {
+            self.playing = false;
+            return Ok(None);
+        };
+        self.playing = playing["is_playing"].as_bool().unwrap_or(false);
+        let item = &playing["item"];
+        let title = item["name"].as_str().unwrap_or_default();
+        if !self.playing || title.is_empty() {
+            return Ok(None);
+        }
+        let artist = item["artists"]
+            .as_array()
+            .map(|artists| {
+                artists
+                    .iter()
+                    .filter_map(|artist| artist["name"].as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+            // Podcast episodes have a show instead of artists
+            .or_else(|| item["show"]["name"].as_str().map(str::to_string))
+            .unwrap_or_default();
+        Ok(Some(SongInfo {
+            artist,
+            title: title.to_string(),
+            album: item["album"]["name"].as_str().map(str::to_string),
+            artwork_url: item["album"]["images"][0]["url"]
+                .as_str()
+                .or_else(|| item["images"][0]["url"].as_str())
+                .map(str::to_string),
+            path: None,
+            duration: item["duration_ms"].as_f64().map(|ms| ms / 1000.0),
+            custom: false,
+        }))
+    }
+}
+
+impl Driver for SpotifyWebDriver {
+    fn fetch_song_info(&mut self) -> Option<SongInfo> {
+        if self.config.refresh_token.is_empty() || Instant::now() < self.retry_at {
+            return self.song.clone();
+        }
+        match self.current_song() {
+            Ok(song) => self.song = song,
+            Err(err) => {
+                warn!("  | spotify-web: {err}");
+                self.retry_at = self.retry_at.max(Instant::now() + RETRY_DELAY);
+            }
+        }
+        self.song.clone()
+    }
+
+    fn control(&mut self, command: &PlayerCommand) -> Result<(), Error> {
+        let play = |agent: &Agent| agent.put(&format!("{API_URL}/play"));
+        let pause = |agent: &Agent| agent.put(&format!("{API_URL}/pause"));
+        let request = match command {
+            PlayerCommand::Play => play(&self.agent),
+            PlayerCommand::Pause => pause(&self.agent),
+            PlayerCommand::Toggle if self.playing => pause(&self.agent),
+            PlayerCommand::Toggle => play(&self.agent),
+            PlayerCommand::Next => self.agent.post(&format!("{API_URL}/next")),
+            PlayerCommand::Previous => self.agent.post(&format!("{API_URL}/previous")),
+            PlayerCommand::Seek { seconds } => self
+                .agent
+                .put(&format!("{API_URL}/seek"))
+                .query("position_ms", &((seconds * 1000.0) as u64).to_string()),
+            PlayerCommand::Volume { level } => self.agent.put(&format!("{API_URL}/volume")).query(
+                "volume_percent",
+                &((level * 100.0).round() as u8).to_string(),
+            ),
+        };
+        self.send(request)?;
+        Ok(())
+    }
+}
@@ -1,51 +1,121 @@
 use std::{
-    fs::{self, File},
-    path::PathBuf,
+    fs, io,
+    path::{Path, PathBuf},
     sync::Arc,
+    thread,
+    time::Duration,
 };
 
-use crate::{config::Config, song::SongInfo, Actor, ActorHandle};
+use crate::{
+    config::{Config, FileConfig, LineEnding, TextEncoding},
+    song::SongInfo,
+    Actor, ActorHandle,
+};
 
+/// An actor that writes the song to a text file, as configured by one of the `files` entries.
 pub struct FileWriterActor {
     config: Arc<Config>,
+    index: usize,
     path: PathBuf,
 }
 
 impl FileWriterActor {
-    pub fn new(path: PathBuf, config: Arc<Config>) -> Self {
-        Self { config, path }
+    /// Creates an actor for the file output with the provided index in the config.
+    pub fn new(path: PathBuf, index: usize, config: Arc<Config>) -> Self {
+        Self {
+            config,
+            index,
+            path,
+        }
+    }
+
+    fn file_config(&self) -> &FileConfig {
+        &self.config.files()[self.index]
+    }
+
+    /// Renders the text to be written to the file.
+    fn render(&self, song: Option<&SongInfo>) -> String {
+        let file_config = self.file_config();
+        let Some(song) = song else {
+            return file_config.empty_text.clone();
+        };
+        let template = file_config
+            .template
+            .as_deref()
+            .unwrap_or(self.config.song_format());
+        let mut song_str = song.format(template);
+        let length = song_str.chars().count();
+        if length < file_config.min_length {
+            song_str.push_str(&" ".repeat(file_config.min_length - length));
+        }
+        song_str.push_str(&file_config.trailing_text);
+        song_str
+    }
+
+    /// Converts the text to bytes with the configured line ending and encoding.
+    fn encode(&self, text: &str) -> Vec<u8> {
+        let file_config = self.file_config();
+        let text = text.replace("\r\n", "\n");
+        let text = match file_config.line_ending {
+            LineEnding::Lf => text,
+            LineEnding::CrLf => text.replace('\n', "\r\n"),
+        };
+        match file_config.encoding {
+            TextEncoding::Utf8 => text.into_bytes(),
+            TextEncoding::Utf8Bom => [&[0xEF, 0xBB, 0xBF], text.as_bytes()].concat(),
+            TextEncoding::Utf16Le => [0xFF, 0xFE]
+                .into_iter()
+                .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+                .collect(),
+        }
+    }
+
+    fn write(&self, song: Option<&SongInfo>) {
+        let contents = self.encode(&self.render(song));
+        if let Err(err) = write_atomic(&self.path, &contents) {
+            warn!("  | Cannot save {:?}: {err:?}", &self.path);
+        }
     }
 }
 
 impl Actor for FileWriterActor {
     type MessageType = Option<SongInfo>;
     fn spawn(self) -> ActorHandle<Self::MessageType> {
-        let (sender, receiver) = flume::unbounded();
+        let (sender, receiver) = flume::unbounded::<Option<SongInfo>>();
         ActorHandle {
             sender,
-            thread_handle: std::thread::spawn(move || {
-                if let Err(err) = File::create(&self.path) {
-                    eprintln!("  | Cannot create or truncate song.txt: {err:?}")
+            thread_handle: thread::spawn(move || {
+                self.write(None);
+                while let Ok(song) = receiver.recv() {
+                    self.write(song.as_ref());
                 }
-                loop {
-                    match receiver.recv() {
-                        Ok(Some(song)) => {
-                            let format = self.config.song_format();
-                            let song_str = format
-                                .replace("{artist}", &song.artist)
-                                .replace("{title}", &song.title);
-                            if let Err(err) = fs::write(&self.path, &song_str) {
-                                eprintln!("  | Cannot save song.txt: {err:?}");
-                            }
-                        }
-                        Ok(None) => {
-                            let _ = File::create(&self.path);
-                        }
-                        _ => break,
-                    }
-                }
-                let _ = File::create(&self.path);
+                self.write(None);
             }),
         }
     }
 }
+
+/// Replaces the contents of a file without readers ever seeing it empty or partially written:
+/// the data goes to a temporary file in the same directory, which is then renamed over the target.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = path.with_file_name(format!(".{file_name}.tmp"));
+    fs::write(&temp_path, contents)?;
+
+    // On Windows, the rename fails while another program is reading the target,
+    // which OBS does periodically. It is holding the file only briefly, so try again.
+    let mut attempts = 0;
+    loop {
+        match fs::rename(&temp_path, path) {
+            Ok(()) => return Ok(()),
+            Err(_) if attempts < 5 => {
+                attempts += 1;
+                thread::sleep(Duration::from_millis(20));
+            }
+            Err(err) => {
+                let _ = fs::remove_file(&temp_path);
+                return Err(err);
+            }
+        }
+    }
+}
@@ -0,0 +1,475 @@
+use std::{
+    path::Path,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Error;
+use flume::{Receiver, Selector};
+use rusqlite::{params, Connection, Row};
+use serde::Serialize;
+
+use crate::{config::Config, json::unix_time, song::SongInfo, Actor, ActorHandle};
+
+pub const HISTORY_FILE_NAME: &str = "history.sqlite";
+
+/// A song that was played, as recorded in the history.
+#[derive(Serialize)]
+pub struct Play {
+    pub id: i64,
+    pub session_id: i64,
+    /// Unix time the song started playing, in seconds.
+    pub started_at: i64,
+    /// Unix time the song stopped playing, or `None` if it still plays
+    /// (or the application did not shut down gracefully).
+    pub ended_at: Option<i64>,
+    /// How long the song was listened to, in seconds, not counting time the app was paused.
+    pub duration: Option<i64>,
+    pub driver: String,
+    pub artist: String,
+    pub title: String,
+    pub album: Option<String>,
+    /// Path to the local file that was played, if known.
+    pub path: Option<String>,
+}
+
+/// A single run of the application.
+#[derive(Serialize)]
+pub struct Session {
+    pub id: i64,
+    pub started_at: i64,
+    pub ended_at: Option<i64>,
+    pub play_count: i64,
+}
+
+#[derive(Serialize)]
+pub struct ArtistCount {
+    pub artist: String,
+    pub play_count: i64,
+    /// Total time listened, in seconds.
+    pub duration: i64,
+}
+
+/// Persistent play history, stored in an SQLite database.
+pub struct History {
+    connection: Connection,
+}
+
+/// Plays recorded by older versions only have their start and end.
+const PLAY_COLUMNS: &str = "id, session_id, started_at, ended_at, \
+                            COALESCE(listened, ended_at - started_at), \
+                            driver, artist, title, album, path";
+
+impl History {
+    /// Opens the history database in the data directory, creating it if needed.
+    pub fn open(data_directory: &Path) -> Result<History, Error> {
+        let connection = Connection::open(data_directory.join(HISTORY_FILE_NAME))?;
+        // The recording actor and readers use separate connections
+        connection.busy_timeout(Duration::from_secs(5))?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        let history = History { connection };
+        history.migrate()?;
+        Ok(history)
+    }
+
+    /// Brings the database schema up to date, tracking its version in `user_version`.
+    fn migrate(&self) -> Result<(), Error> {
+        let version: u32 = self
+            .connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version < 1 {
+            self.connection.execute_batch(
+                "CREATE TABLE IF NOT EXISTS sessions (
+                    id INTEGER PRIMARY KEY,
+                    started_at INTEGER NOT NULL,
+                    ended_at INTEGER
+                );
+                CREATE TABLE IF NOT EXISTS plays (
+                    id INTEGER PRIMARY KEY,
+                    session_id INTEGER NOT NULL REFERENCES sessions(id),
+                    started_at INTEGER NOT NULL,
+                    ended_at INTEGER,
+                    driver TEXT NOT NULL,
+                    artist TEXT NOT NULL,
+                    title TEXT NOT NULL,
+                    album TEXT
+                );
+                CREATE INDEX IF NOT EXISTS plays_started_at ON plays(started_at);
+                PRAGMA user_version = 1;",
+            )?;
+        }
+        if version < 2 {
+            self.connection.execute_batch(
+                "ALTER TABLE plays ADD COLUMN path TEXT;
+                PRAGMA user_version = 2;",
+            )?;
+        }
+        if version < 3 {
+            self.connection.execute_batch(
+                "ALTER TABLE plays ADD COLUMN listened INTEGER;
+                PRAGMA user_version = 3;",
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn start_session(&self, started_at: i64) -> Result<i64, Error> {
+        self.connection.execute(
+            "INSERT INTO sessions (started_at) VALUES (?1)",
+            params![started_at],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    pub fn end_session(&self, session_id: i64, ended_at: i64) -> Result<(), Error> {
+        self.connection.execute(
+            "UPDATE sessions SET ended_at = ?2 WHERE id = ?1",
+            params![session_id, ended_at],
+        )?;
+        Ok(())
+    }
+
+    /// Records that a song started playing. Returns the ID of the play.
+    pub fn start_play(
+        &self,
+        session_id: i64,
+        driver: &str,
+        song: &SongInfo,
+        started_at: i64,
+    ) -> Result<i64, Error> {
+        self.connection.execute(
+            "INSERT INTO plays (session_id, started_at, driver, artist, title, album, path)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                session_id,
+                started_at,
+                driver,
+                song.artist,
+                song.title,
+                song.album,
+                song.path.as_ref().map(|p| p.to_string_lossy())
+            ],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    /// Records that a song stopped playing after being listened to for `listened` seconds.
+    pub fn end_play(&self, play_id: i64, ended_at: i64, listened: i64) -> Result<(), Error> {
+        self.connection.execute(
+            "UPDATE plays SET ended_at = ?2, listened = ?3 WHERE id = ?1",
+            params![play_id, ended_at, listened],
+        )?;
+        Ok(())
+    }
+
+    /// Returns the last songs played, most recent first.
+    pub fn last(&self, count: u32) -> Result<Vec<Play>, Error> {
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT {PLAY_COLUMNS} FROM plays ORDER BY started_at DESC, id DESC LIMIT ?1"
+        ))?;
+        let plays = statement.query_map(params![count], play_from_row)?;
+        Ok(plays.collect::<Result<_, _>>()?)
+    }
+
+    /// Returns songs played in a session, in order.
+    pub fn session(&self, session_id: i64) -> Result<Vec<Play>, Error> {
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT {PLAY_COLUMNS} FROM plays WHERE session_id = ?1 ORDER BY started_at, id"
+        ))?;
+        let plays = statement.query_map(params![session_id], play_from_row)?;
+        Ok(plays.collect::<Result<_, _>>()?)
+    }
+
+    /// Returns songs that started playing between two Unix times, in order.
+    pub fn between(&self, from: i64, to: i64) -> Result<Vec<Play>, Error> {
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT {PLAY_COLUMNS} FROM plays WHERE started_at >= ?1 AND started_at < ?2 \
+            ORDER BY started_at, id"
+        ))?;
+        let plays = statement.query_map(params![from, to], play_from_row)?;
+        Ok(plays.collect::<Result<_, _>>()?)
+    }
+
+    /// Returns the start time of a session, if it exists.
+    pub fn session_start(&self, session_id: i64) -> Result<Option<i64>, Error> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT started_at FROM sessions WHERE id = ?1")?;
+        let mut rows = statement.query_map(params![session_id], |row| row.get(0))?;
+        Ok(rows.next().transpose()?)
+    }
+
+    /// Returns the most recent sessions, most recent first.
+    pub fn sessions(&self, count: u32) -> Result<Vec<Session>, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT s.id, s.started_at, s.ended_at, COUNT(p.id)
+            FROM sessions s LEFT JOIN plays p ON p.session_id = s.id
+            GROUP BY s.id ORDER BY s.started_at DESC, s.id DESC LIMIT ?1",
+        )?;
+        let sessions = statement.query_map(params![count], |row| {
+            Ok(Session {
+                id: row.get(0)?,
+                started_at: row.get(1)?,
+                ended_at: row.get(2)?,
+                play_count: row.get(3)?,
+            })
+        })?;
+        Ok(sessions.collect::<Result<_, _>>()?)
+    }
+
+    /// Returns artists played the most between two Unix times.
+    pub fn top_artists(&self, from: i64, to: i64, count: u32) -> Result<Vec<ArtistCount>, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT artist, COUNT(*), COALESCE(SUM(COALESCE(listened, ended_at - started_at)), 0)
+            FROM plays WHERE started_at >= ?1 AND started_at < ?2
+            GROUP BY artist ORDER BY COUNT(*) DESC, artist LIMIT ?3",
+        )?;
+        let artists = statement.query_map(params![from, to, count], |row| {
+            Ok(ArtistCount {
+                artist: row.get(0)?,
+                play_count: row.get(1)?,
+                duration: row.get(2)?,
+            })
+        })?;
+        Ok(artists.collect::<Result<_, _>>()?)
+    }
+
+    /// Converts Unix time to a date such as `2024-05-01 18:30`, in UTC.
+    pub fn format_time(&self, time: i64) -> Result<String, Error> {
+        Ok(self.connection.query_row(
+            "SELECT strftime('%Y-%m-%d %H:%M', ?1, 'unixepoch')",
+            params![time],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Converts a time such as `2024-05-01` or `2024-05-01 18:30` to Unix time.
+    /// Unix time itself is accepted as well.
+    pub fn parse_time(&self, time: &str) -> Result<Option<i64>, Error> {
+        if let Ok(time) = time.parse() {
+            return Ok(Some(time));
+        }
+        Ok(self.connection.query_row(
+            "SELECT CAST(strftime('%s', ?1) AS INTEGER)",
+            params![time],
+            |row| row.get(0),
+        )?)
+    }
+}
+
+fn play_from_row(row: &Row) -> rusqlite::Result<Play> {
+    Ok(Play {
+        id: row.get(0)?,
+        session_id: row.get(1)?,
+        started_at: row.get(2)?,
+        ended_at: row.get(3)?,
+        duration: row.get(4)?,
+        driver: row.get(5)?,
+        artist: row.get(6)?,
+        title: row.get(7)?,
+        album: row.get(8)?,
+        path: row.get(9)?,
+    })
+}
+
+/// Tells the history about something other than a song change.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HistoryEvent {
+    Session(SessionMarker),
+    /// The app stopped or started sending song changes again,
+    /// time in between does not count as listened.
+    Paused(bool),
+}
+
+/// Marks the boundaries of a session, e.g. a DJ set within a longer stream.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SessionMarker {
+    /// Ends the current session and starts a new one right away.
+    Start,
+    /// Ends the current session. A new one starts with the next song.
+    End,
+}
+
+/// An actor that records every song change in the [History].
+/// A session is started when the actor starts and whenever a [SessionMarker] asks for one.
+pub struct HistoryActor {
+    config: Arc<Config>,
+    history: History,
+    events: Receiver<HistoryEvent>,
+}
+
+impl HistoryActor {
+    pub fn new(history: History, events: Receiver<HistoryEvent>, config: Arc<Config>) -> Self {
+        Self {
+            config,
+            history,
+            events,
+        }
+    }
+}
+
+/// Keeps track of the session and play currently being recorded.
+struct Recorder<'a> {
+    history: &'a History,
+    driver: &'a str,
+    session_id: Option<i64>,
+    play_id: Option<i64>,
+    song: Option<SongInfo>,
+    paused: bool,
+    /// Time the current play was listened to before it was last paused.
+    listened: Duration,
+    /// When the current play was last started or resumed, `None` while paused.
+    resumed_at: Option<Instant>,
+}
+
+impl Recorder<'_> {
+    fn set_song(&mut self, song: Option<SongInfo>) {
+        self.end_play();
+        self.song = song.filter(|song| !song.custom);
+        self.start_play();
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        if paused == self.paused {
+            return;
+        }
+        self.paused = paused;
+        if paused {
+            self.listened += self
+                .resumed_at
+                .take()
+                .map_or(Duration::ZERO, |t| t.elapsed());
+        } else if self.play_id.is_some() {
+            self.resumed_at = Some(Instant::now());
+        }
+    }
+
+    fn start_session(&mut self) {
+        self.end_session();
+        self.session_id = log_error(self.history.start_session(now()));
+        self.start_play();
+    }
+
+    fn end_session(&mut self) {
+        self.end_play();
+        if let Some(session_id) = self.session_id.take() {
+            log_error(self.history.end_session(session_id, now()));
+        }
+    }
+
+    fn start_play(&mut self) {
+        let Some(song) = &self.song else {
+            return;
+        };
+        if self.session_id.is_none() {
+            self.session_id = log_error(self.history.start_session(now()));
+        }
+        if let Some(session_id) = self.session_id {
+            let play = self
+                .history
+                .start_play(session_id, self.driver, song, now());
+            self.play_id = log_error(play);
+            self.listened = Duration::ZERO;
+            self.resumed_at = (!self.paused).then(Instant::now);
+        }
+    }
+
+    fn end_play(&mut self) {
+        let listened = self.listened
+            + self
+                .resumed_at
+                .take()
+                .map_or(Duration::ZERO, |t| t.elapsed());
+        if let Some(play_id) = self.play_id.take() {
+            let listened = listened.as_secs() as i64;
+            log_error(self.history.end_play(play_id, now(), listened));
+        }
+    }
+}
+
+fn now() -> i64 {
+    unix_time() as i64
+}
+
+fn log_error<T>(result: Result<T, Error>) -> Option<T> {
+    result
+        .map_err(|err| warn!("  | Cannot update song history: {err}"))
+        .ok()
+}
+
+enum Input {
+    Song(Option<SongInfo>),
+    Event(HistoryEvent),
+    Closed,
+}
+
+impl Actor for HistoryActor {
+    type MessageType = Option<SongInfo>;
+    fn spawn(self) -> ActorHandle<Self::MessageType> {
+        let (sender, receiver) = flume::unbounded::<Option<SongInfo>>();
+        ActorHandle {
+            sender,
+            thread_handle: thread::spawn(move || {
+                let mut recorder = Recorder {
+                    history: &self.history,
+                    driver: self.config.driver_name(),
+                    session_id: None,
+                    play_id: None,
+                    song: None,
+                    paused: false,
+                    listened: Duration::ZERO,
+                    resumed_at: None,
+                };
+                recorder.start_session();
+
+                loop {
+                    let input = Selector::new()
+                        .recv(&receiver, |r| r.map(Input::Song).unwrap_or(Input::Closed))
+                        .recv(&self.events, |r| {
+                            r.map(Input::Event).unwrap_or(Input::Closed)
+                        })
+                        .wait();
+                    match input {
+                        Input::Song(song) => recorder.set_song(song),
+                        Input::Event(HistoryEvent::Session(SessionMarker::Start)) => {
+                            recorder.start_session()
+                        }
+                        Input::Event(HistoryEvent::Session(SessionMarker::End)) => {
+                            recorder.end_session()
+                        }
+                        Input::Event(HistoryEvent::Paused(paused)) => recorder.set_paused(paused),
+                        Input::Closed => break,
+                    }
+                }
+
+                recorder.end_session();
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn in_memory() -> History {
+        let history = History {
+            connection: Connection::open_in_memory().unwrap(),
+        };
+        history.migrate().unwrap();
+        history
+    }
+
+    #[test]
+    fn parses_dates_and_unix_time() {
+        let history = in_memory();
+        assert_eq!(history.parse_time("1714521600").unwrap(), Some(1714521600));
+        assert_eq!(history.parse_time("2024-05-01").unwrap(), Some(1714521600));
+        assert_eq!(
+            history.parse_time("2024-05-01 18:30").unwrap(),
+            Some(1714521600 + 18 * 3600 + 30 * 60)
+        );
+        assert_eq!(history.parse_time("yesterday").unwrap(), None);
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    io::Write,
+    process::{Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Error};
+use flume::Receiver;
+
+use crate::{
+    config::{Config, HookConcurrency, HookConfig},
+    json::{NowPlaying, PlaybackState},
+    process::wait_timeout,
+    song::SongInfo,
+    Actor, ActorHandle,
+};
+
+/// A command run waiting to be started.
+struct Run {
+    /// Environment variables describing the song.
+    env: Vec<(&'static str, String)>,
+    /// The `nowplaying.json` document, written to the standard input.
+    json: String,
+}
+
+/// An actor that runs a command on every song change, as configured by one of the `hooks` entries.
+/// The song is passed in `CURRENTSONG_*` environment variables and as JSON on the standard input.
+pub struct HookActor {
+    config: Arc<Config>,
+    index: usize,
+    /// Whether a run has been handed to the worker and has not finished yet.
+    busy: AtomicBool,
+}
+
+impl HookActor {
+    /// Creates an actor for the hook with the provided index in the config.
+    pub fn new(index: usize, config: Arc<Config>) -> Self {
+        Self {
+            config,
+            index,
+            busy: AtomicBool::new(false),
+        }
+    }
+
+    fn hook_config(&self) -> &HookConfig {
+        &self.config.hooks()[self.index]
+    }
+
+    /// Prepares the command run for a song change.
+    fn run_for(&self, song: Option<&SongInfo>) -> Run {
+        let now_playing = NowPlaying::new(song);
+        let empty = SongInfo::default();
+        let info = song.unwrap_or(&empty);
+        let state = match now_playing.state {
+            PlaybackState::Playing => "playing",
+            PlaybackState::Stopped => "stopped",
+        };
+        let text = match song {
+            Some(song) => song.format(self.config.song_format()),
+            None => String::new(),
+        };
+        let env = vec![
+            ("CURRENTSONG_STATE", state.to_string()),
+            ("CURRENTSONG_ARTIST", info.artist.clone()),
+            ("CURRENTSONG_TITLE", info.title.clone()),
+            ("CURRENTSONG_ALBUM", info.album.clone().unwrap_or_default()),
+            ("CURRENTSONG_TEXT", text),
+            (
+                "CURRENTSONG_ARTWORK_URL",
+                info.artwork_url.clone().unwrap_or_default(),
+            ),
+            (
+                "CURRENTSONG_FILE",
+                info.path
+                    .as_ref()
+                    .map(|p| p.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+            ),
+            (
+                "CURRENTSONG_DURATION",
+                info.duration.map(|d| d.to_string()).unwrap_or_default(),
+            ),
+            ("CURRENTSONG_TIMESTAMP", now_playing.timestamp.to_string()),
+        ];
+        Run {
+            env,
+            json: serde_json::to_string(&now_playing).unwrap_or_default(),
+        }
+    }
+
+    /// Runs commands one by one as they arrive.
+    fn execute(&self, runs: Receiver<Run>) {
+        let hook_config = self.hook_config();
+        let name = hook_config.command.join(" ");
+        for run in runs.iter() {
+            let result = self.execute_one(run);
+            self.busy.store(false, Ordering::SeqCst);
+            match result {
+                Ok(status) if status.success() => {}
+                Ok(status) => match status.code() {
+                    Some(code) => warn!("  | Hook `{name}` exited with code {code}"),
+                    None => warn!("  | Hook `{name}` was terminated: {status}"),
+                },
+                Err(err) => warn!("  | Hook `{name}` failed: {err}"),
+            }
+        }
+    }
+
+    fn execute_one(&self, run: Run) -> Result<ExitStatus, Error> {
+        let hook_config = self.hook_config();
+        let (program, args) = hook_config
+            .command
+            .split_first()
+            .ok_or_else(|| anyhow!("no command set"))?;
+
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .stdin(if hook_config.stdin {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::null());
+        if hook_config.env {
+            command.envs(run.env);
+        }
+        #[cfg(target_os = "windows")]
+        {
+            use std::os::windows::process::CommandExt;
+            // Do not flash a console window for every run
+            const CREATE_NO_WINDOW: u32 = 0x08000000;
+            command.creation_flags(CREATE_NO_WINDOW);
+        }
+
+        let mut child = command.spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // The command may exit without reading its input, that is fine
+            let _ = stdin.write_all(run.json.as_bytes());
+        }
+        wait_timeout(&mut child, Duration::from_secs(hook_config.timeout_secs))
+    }
+}
+
+impl Actor for HookActor {
+    type MessageType = Option<SongInfo>;
+    fn spawn(self) -> ActorHandle<Self::MessageType> {
+        let (sender, receiver) = flume::unbounded::<Option<SongInfo>>();
+        ActorHandle {
+            sender,
+            thread_handle: thread::spawn(move || {
+                let this = Arc::new(self);
+                let (run_sender, run_receiver) = flume::unbounded();
+                let worker = this.clone();
+                thread::spawn(move || worker.execute(run_receiver));
+
+                while let Ok(song) = receiver.recv() {
+                    let was_busy = this.busy.swap(true, Ordering::SeqCst);
+                    if was_busy && this.hook_config().concurrency == HookConcurrency::Skip {
+                        warn!(
+                            "  | Hook `{}` is still running, skipping a song change",
+                            this.hook_config().command.join(" ")
+                        );
+                        continue;
+                    }
+                    let _ = run_sender.send(this.run_for(song.as_ref()));
+                }
+            }),
+        }
+    }
+}
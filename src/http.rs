@@ -0,0 +1,100 @@
+use std::{error::Error, io, sync::Arc, thread, time::Duration};
+
+use tiny_http::{Header, Server};
+use ureq::{Agent, AgentBuilder};
+
+/// How many times to try listening on a port that is in use, a tenth of a second apart.
+const LISTEN_ATTEMPTS: u32 = 20;
+
+/// Creates an HTTP client that uses the platform's TLS implementation.
+pub fn agent(timeout: Duration) -> Agent {
+    agent_builder(timeout).build()
+}
+
+/// Prepares an HTTP client like [agent], for callers that need to change more settings.
+pub fn agent_builder(timeout: Duration) -> AgentBuilder {
+    let builder = AgentBuilder::new().timeout(timeout);
+    match native_tls::TlsConnector::new() {
+        Ok(connector) => builder.tls_connector(Arc::new(connector)),
+        Err(err) => {
+            warn!("  | Cannot initialize TLS, HTTPS requests will fail: {err:?}");
+            builder
+        }
+    }
+}
+
+/// Starts an HTTP server on a local port.
+/// A server shutting down, e.g. after the config was reloaded, may hold the port for a moment,
+/// so a port in use is tried again for a while.
+pub fn listen(port: u16) -> Result<Server, Box<dyn Error + Send + Sync>> {
+    let mut attempt = 1;
+    loop {
+        match Server::http(("127.0.0.1", port)) {
+            Err(err)
+                if attempt < LISTEN_ATTEMPTS
+                    && err
+                        .downcast_ref::<io::Error>()
+                        .is_some_and(|err| err.kind() == io::ErrorKind::AddrInUse) =>
+            {
+                attempt += 1;
+                thread::sleep(Duration::from_millis(100));
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Finds the value of a request header.
+pub fn header<'a>(headers: &'a [Header], field: &'static str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|header| header.field.equiv(field))
+        .map(|header| header.value.as_str())
+}
+
+/// Whether a `Host` header names this machine on the port, such as `localhost:8080`.
+/// A web page can point its own domain at 127.0.0.1 (DNS rebinding),
+/// but its requests then still carry that domain.
+pub fn is_local_host(host: &str, port: u16) -> bool {
+    let name = match host.rsplit_once(':') {
+        Some((name, host_port)) if !host_port.ends_with(']') => {
+            if host_port.parse() != Ok(port) {
+                return false;
+            }
+            name
+        }
+        _ => host,
+    };
+    ["localhost", "127.0.0.1", "[::1]"]
+        .iter()
+        .any(|local| name.eq_ignore_ascii_case(local))
+}
+
+/// Compares secrets in time that does not depend on where they differ.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_only_local_hosts_on_the_port() {
+        assert!(is_local_host("localhost:8080", 8080));
+        assert!(is_local_host("127.0.0.1:8080", 8080));
+        assert!(is_local_host("[::1]:8080", 8080));
+        assert!(is_local_host("LocalHost:8080", 8080));
+        assert!(!is_local_host("localhost:8081", 8080));
+        assert!(!is_local_host("attacker.example:8080", 8080));
+        assert!(!is_local_host("localhost.attacker.example:8080", 8080));
+        assert!(!is_local_host("", 8080));
+    }
+
+    #[test]
+    fn compares_secrets() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+    }
+}
@@ -0,0 +1,119 @@
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use crate::{config::Config, file::write_atomic, song::SongInfo, Actor, ActorHandle};
+
+/// Version of the [NowPlaying] document.
+/// Bumped only on changes that break existing readers, new fields may be added at any time.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A structured snapshot of the playback state, as written to `nowplaying.json`.
+/// See the README for the schema.
+#[derive(Serialize)]
+pub struct NowPlaying<'a> {
+    pub schema_version: u32,
+    /// Unix time of the change, in seconds.
+    pub timestamp: u64,
+    pub state: PlaybackState,
+    pub song: Option<&'a SongInfo>,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaybackState {
+    Playing,
+    Stopped,
+}
+
+impl<'a> NowPlaying<'a> {
+    pub fn new(song: Option<&'a SongInfo>) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            timestamp: unix_time(),
+            state: match song {
+                Some(_) => PlaybackState::Playing,
+                None => PlaybackState::Stopped,
+            },
+            song,
+        }
+    }
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// An actor that writes the song as a JSON document,
+/// and optionally appends every change to a JSON lines log.
+pub struct JsonWriterActor {
+    path: PathBuf,
+    log_path: Option<PathBuf>,
+}
+
+impl JsonWriterActor {
+    pub fn new(data_directory: &Path, config: Arc<Config>) -> Self {
+        let json_config = config.json();
+        Self {
+            path: data_directory.join(&json_config.path),
+            log_path: json_config
+                .log_path
+                .as_ref()
+                .map(|p| data_directory.join(p)),
+        }
+    }
+
+    fn write(&self, song: Option<&SongInfo>) {
+        let now_playing = NowPlaying::new(song);
+        match serde_json::to_vec_pretty(&now_playing) {
+            Ok(json) => {
+                if let Err(err) = write_atomic(&self.path, &json) {
+                    warn!("  | Cannot save {:?}: {err:?}", &self.path);
+                }
+            }
+            Err(err) => warn!("  | Cannot serialize song: {err:?}"),
+        }
+
+        if let Some(log_path) = &self.log_path {
+            let result = serde_json::to_string(&now_playing)
+                .map_err(|err| err.into())
+                .and_then(|line| {
+                    let mut log = OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(log_path)?;
+                    writeln!(log, "{line}")
+                });
+            if let Err(err) = result {
+                warn!("  | Cannot append to {log_path:?}: {err:?}");
+            }
+        }
+    }
+}
+
+impl Actor for JsonWriterActor {
+    type MessageType = Option<SongInfo>;
+    fn spawn(self) -> ActorHandle<Self::MessageType> {
+        let (sender, receiver) = flume::unbounded::<Option<SongInfo>>();
+        ActorHandle {
+            sender,
+            thread_handle: thread::spawn(move || {
+                self.write(None);
+                while let Ok(song) = receiver.recv() {
+                    self.write(song.as_ref());
+                }
+                self.write(None);
+            }),
+        }
+    }
+}
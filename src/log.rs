@@ -0,0 +1,58 @@
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use anyhow::{anyhow, Error};
+
+/// How much is written to standard error, from the least to the most.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum LogLevel {
+    /// Nothing but errors that stop the app.
+    Off,
+    /// Problems the app works around, such as an output that cannot reach its service.
+    Warn,
+    /// Also what the app is doing, such as which plugins were loaded.
+    Info,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(level: LogLevel) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+impl FromStr for LogLevel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" | "quiet" => Ok(LogLevel::Off),
+            "warn" | "warning" | "error" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            _ => Err(anyhow!("unknown log level: {s}")),
+        }
+    }
+}
+
+/// Writes a problem the app works around to standard error.
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::LogLevel::Warn) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Writes what the app is doing to standard error.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::LogLevel::Info) {
+            eprintln!($($arg)*);
+        }
+    };
+}
@@ -1,19 +1,230 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use std::{path::PathBuf, process::ExitCode};
+
 use actor::{Actor, ActorHandle};
-use app::AppBuilder;
+use app::{AppBuilder, CONFIG_FILE_NAME};
+use clap::{Args, Parser, Subcommand};
+use console::OutputFormat;
+use log::LogLevel;
+use tracklist::Format;
+
+// Declared first, so that its macros can be used in the other modules
+#[macro_use]
+mod log;
 
 mod actor;
 mod app;
+mod artwork;
+mod cli;
 mod config;
 mod console;
+mod control;
+#[cfg(unix)]
+mod dashboard;
+mod discord;
 mod driver;
 mod file;
+mod history;
+mod hook;
+mod http;
+mod json;
+mod mqtt;
+#[cfg(target_os = "linux")]
+mod notification;
+mod obs;
+mod plugin;
 mod process;
+mod scrobble;
+mod server;
 mod song;
+mod song_override;
+mod status;
+mod tracklist;
+mod twitch;
+mod webhook;
+#[cfg(target_os = "windows")]
 mod window;
 
-fn main() {
-    let app = AppBuilder::new().build();
-    app.run();
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    run: RunArgs,
+    /// Directory holding the config file, the song history and other data.
+    #[arg(long, global = true)]
+    data_dir: Option<PathBuf>,
+    /// Config file to use instead of config.json in the data directory.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Driver to use instead of the one set in the config file.
+    #[arg(long, global = true)]
+    driver: Option<String>,
+    /// How much to write to standard error. One of: off, warn, info.
+    #[arg(long, global = true, default_value = "info")]
+    log_level: LogLevel,
+}
+
+#[derive(Args)]
+struct RunArgs {
+    /// How to write songs to standard output. One of: text (the default), jsonl.
+    /// With jsonl, every change is a JSON object on its own line and diagnostics go to standard error.
+    #[arg(long)]
+    output: Option<OutputFormat>,
+    /// Print the current song and exit.
+    #[arg(long)]
+    once: bool,
+    /// Exit if the config file cannot be read or is invalid,
+    /// instead of running with the default config until it is fixed.
+    #[arg(long)]
+    strict_config: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Runs until closed, sending song changes to the outputs. This is the default.
+    Run(RunArgs),
+    /// Prints the current song, as reported by the driver, and exits.
+    Now {
+        /// One of: text, jsonl.
+        #[arg(long, default_value = "text")]
+        output: OutputFormat,
+    },
+    /// Shows, checks or edits the config file.
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Shows the drivers that can be used.
+    #[command(subcommand)]
+    Drivers(DriversCommand),
+    /// Prints the last songs from the song history.
+    History {
+        /// How many songs to print.
+        #[arg(long, short = 'n', default_value = "20")]
+        count: u32,
+        /// ID of a session to print all songs of, instead of the last ones.
+        #[arg(long)]
+        session: Option<i64>,
+        /// Print the songs as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Sends a command to a running instance and prints its reply.
+    /// One of: status, refresh, pause, resume, override TEXT, clear-override, reload, exit,
+    /// player COMMAND, session start|end.
+    Ctl {
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Exports a tracklist from the song history.
+    Export {
+        /// One of: youtube, cue, m3u8, xspf.
+        #[arg(long, default_value = "youtube")]
+        format: Format,
+        /// ID of the session to export. Overrides --from and --to.
+        #[arg(long)]
+        session: Option<i64>,
+        /// Start of the time range, as Unix time or a date like "2024-05-01 18:30" (UTC).
+        #[arg(long)]
+        from: Option<String>,
+        /// End of the time range (exclusive).
+        #[arg(long)]
+        to: Option<String>,
+        /// Time the stream started, which chapter times are relative to.
+        #[arg(long)]
+        start: Option<String>,
+        /// File to write the tracklist to, instead of standard output.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Prints the path to the config file.
+    Path,
+    /// Prints the config, including defaults of settings missing from the file.
+    Show,
+    /// Checks that the config file can be read.
+    Validate,
+    /// Opens the config file in $VISUAL or $EDITOR and checks it afterwards.
+    Edit,
+}
+
+#[derive(Subcommand)]
+enum DriversCommand {
+    /// Lists built-in drivers, driver scripts and drivers of plugins, marking the one in use.
+    List,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    log::set_level(cli.log_level);
+    let data_directory = cli.data_dir.clone().unwrap_or_else(app::data_directory);
+    let config_path = cli
+        .config
+        .clone()
+        .unwrap_or_else(|| data_directory.join(CONFIG_FILE_NAME));
+    // Release builds have no console on Windows, so printed text would go nowhere
+    #[cfg(target_os = "windows")]
+    if cli.command.is_some() || cli.run.output.is_some() || cli.run.once || cli.run.strict_config {
+        cli::attach_console();
+    }
+    let run = |args: RunArgs| {
+        let app = AppBuilder::new()
+            .data_directory(cli.data_dir.clone())
+            .config_path(cli.config.clone())
+            .driver(cli.driver.clone())
+            .output_format(args.output.unwrap_or(OutputFormat::Text))
+            .once(args.once)
+            .strict_config(args.strict_config)
+            .build()?;
+        app.run();
+        Ok(())
+    };
+
+    let result = match cli.command {
+        None => run(cli.run),
+        Some(Command::Run(args)) => run(args),
+        Some(Command::Now { output }) => run(RunArgs {
+            output: Some(output),
+            once: true,
+            strict_config: false,
+        }),
+        Some(Command::Config(command)) => match command {
+            ConfigCommand::Path => {
+                println!("{}", config_path.display());
+                Ok(())
+            }
+            ConfigCommand::Show => cli::config_show(&config_path, cli.driver),
+            ConfigCommand::Validate => cli::config_validate(&config_path),
+            ConfigCommand::Edit => cli::config_edit(&config_path),
+        },
+        Some(Command::Drivers(DriversCommand::List)) => {
+            cli::drivers_list(&data_directory, &config_path, cli.driver)
+        }
+        Some(Command::History {
+            count,
+            session,
+            json,
+        }) => cli::history(&data_directory, count, session, json),
+        Some(Command::Ctl { command }) => cli::ctl(&data_directory, &command),
+        Some(Command::Export {
+            format,
+            session,
+            from,
+            to,
+            start,
+            output,
+        }) => cli::export(&data_directory, format, session, from, to, start, output),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -0,0 +1,371 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Error};
+use flume::RecvTimeoutError;
+use serde_json::json;
+
+use crate::{
+    config::{Config, MqttConfig},
+    json::NowPlaying,
+    song::SongInfo,
+    Actor, ActorHandle,
+};
+
+/// Delay before reconnecting after the connection is lost.
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// How long to wait for the broker, so that an unreachable one does not hold up the actor.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Home Assistant template showing the song from the `state` document.
+const SONG_TEMPLATE: &str = "{{ value_json.song.artist ~ ' - ' ~ value_json.song.title \
+                             if value_json.song else 'None' }}";
+
+const ONLINE: &str = "online";
+const OFFLINE: &str = "offline";
+
+/// A message to publish, retained by the broker.
+struct Message<'a> {
+    topic: &'a str,
+    payload: &'a [u8],
+}
+
+/// A minimal MQTT 3.1.1 client: it only publishes, with QoS 0.
+struct Connection {
+    stream: TcpStream,
+}
+
+impl Connection {
+    fn open(config: &MqttConfig, client_id: &str, will: Message) -> Result<Connection, Error> {
+        let address = (config.host.as_str(), config.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("cannot resolve {}", config.host))?;
+        let mut stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+        stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CONNECT_TIMEOUT))?;
+
+        let mut flags = 0x02; // Clean session
+        flags |= 0x04 | 0x20; // Retained will with QoS 0
+        let mut payload = Vec::new();
+        write_string(&mut payload, client_id.as_bytes());
+        write_string(&mut payload, will.topic.as_bytes());
+        write_string(&mut payload, will.payload);
+        if let Some(username) = &config.username {
+            flags |= 0x80;
+            write_string(&mut payload, username.as_bytes());
+            if let Some(password) = &config.password {
+                flags |= 0x40;
+                write_string(&mut payload, password.as_bytes());
+            }
+        }
+
+        let mut body = Vec::new();
+        write_string(&mut body, b"MQTT");
+        body.push(4); // Protocol level of 3.1.1
+        body.push(flags);
+        body.extend_from_slice(&config.keep_alive_secs.to_be_bytes());
+        body.extend_from_slice(&payload);
+        stream.write_all(&packet(0x10, &body))?;
+
+        let mut connack = [0; 4];
+        stream.read_exact(&mut connack)?;
+        if connack[0] != 0x20 {
+            return Err(anyhow!("unexpected response to CONNECT"));
+        }
+        match connack[3] {
+            0 => Ok(Connection { stream }),
+            4 | 5 => Err(anyhow!("not authorized, check username and password")),
+            code => Err(anyhow!("connection refused with code {code}")),
+        }
+    }
+
+    fn publish(&mut self, message: Message) -> Result<(), Error> {
+        let mut body = Vec::new();
+        write_string(&mut body, message.topic.as_bytes());
+        body.extend_from_slice(message.payload);
+        self.stream.write_all(&packet(0x31, &body))?;
+        Ok(())
+    }
+
+    fn ping(&mut self) -> Result<(), Error> {
+        self.stream.write_all(&packet(0xC0, &[]))?;
+        let mut pingresp = [0; 2];
+        self.stream.read_exact(&mut pingresp)?;
+        Ok(())
+    }
+
+    fn disconnect(mut self) {
+        let _ = self.stream.write_all(&packet(0xE0, &[]));
+    }
+}
+
+/// Builds a packet with a fixed header, which holds its type, flags and remaining length.
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    let mut length = body.len();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+/// Writes a length-prefixed string.
+fn write_string(buffer: &mut Vec<u8>, value: &[u8]) {
+    buffer.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    buffer.extend_from_slice(value);
+}
+
+/// An actor that publishes the song to an MQTT broker.
+///
+/// Under `<topic_prefix>/<instance>`, it publishes retained messages to `state`
+/// (the `nowplaying.json` document), to `artist`, `title`, `album` and `playing`,
+/// and `online` or `offline` to `availability`.
+pub struct MqttActor {
+    config: Arc<Config>,
+}
+
+impl MqttActor {
+    pub fn new(config: Arc<Config>) -> Self {
+        Self { config }
+    }
+
+    fn mqtt_config(&self) -> &MqttConfig {
+        self.config.mqtt()
+    }
+
+    fn topic(&self, name: &str) -> String {
+        let mqtt_config = self.mqtt_config();
+        format!(
+            "{}/{}/{name}",
+            mqtt_config.topic_prefix, mqtt_config.instance
+        )
+    }
+
+    fn connect(&self) -> Result<Connection, Error> {
+        let mqtt_config = self.mqtt_config();
+        let client_id = mqtt_config
+            .client_id
+            .clone()
+            .unwrap_or_else(|| format!("currentsong-{}", mqtt_config.instance));
+        let availability = self.topic("availability");
+        let will = Message {
+            topic: &availability,
+            payload: OFFLINE.as_bytes(),
+        };
+        let mut connection = Connection::open(mqtt_config, &client_id, will)?;
+        connection.publish(Message {
+            topic: &availability,
+            payload: ONLINE.as_bytes(),
+        })?;
+        if mqtt_config.home_assistant_discovery {
+            self.publish_discovery(&mut connection)?;
+        }
+        Ok(connection)
+    }
+
+    fn publish_song(
+        &self,
+        connection: &mut Connection,
+        song: Option<&SongInfo>,
+    ) -> Result<(), Error> {
+        let state = serde_json::to_vec(&NowPlaying::new(song))?;
+        let fields = [
+            ("state", state.as_slice()),
+            (
+                "artist",
+                song.map(|s| s.artist.as_bytes()).unwrap_or_default(),
+            ),
+            (
+                "title",
+                song.map(|s| s.title.as_bytes()).unwrap_or_default(),
+            ),
+            (
+                "album",
+                song.and_then(|s| s.album.as_deref())
+                    .unwrap_or_default()
+                    .as_bytes(),
+            ),
+            ("playing", if song.is_some() { b"true" } else { b"false" }),
+        ];
+        for (name, payload) in fields {
+            let topic = self.topic(name);
+            connection.publish(Message {
+                topic: &topic,
+                payload,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Publishes sensors for Home Assistant, see <https://www.home-assistant.io/integrations/mqtt/#mqtt-discovery>.
+    fn publish_discovery(&self, connection: &mut Connection) -> Result<(), Error> {
+        let mqtt_config = self.mqtt_config();
+        let instance = &mqtt_config.instance;
+        let device = json!({
+            "identifiers": [format!("currentsong_{instance}")],
+            "name": format!("CurrentSong ({instance})"),
+            "manufacturer": "CurrentSong",
+        });
+        let sensors = [
+            ("song", "Song", "state", Some(SONG_TEMPLATE)),
+            ("artist", "Artist", "artist", None),
+            ("title", "Title", "title", None),
+            ("album", "Album", "album", None),
+        ];
+        for (id, name, field, value_template) in sensors {
+            let mut config = json!({
+                "name": name,
+                "unique_id": format!("currentsong_{instance}_{id}"),
+                "state_topic": self.topic(field),
+                "availability_topic": self.topic("availability"),
+                "icon": "mdi:music",
+                "device": device,
+            });
+            if let Some(value_template) = value_template {
+                config["value_template"] = json!(value_template);
+                config["json_attributes_topic"] = json!(self.topic("state"));
+                config["json_attributes_template"] = json!("{{ value_json.song | tojson }}");
+            }
+            let topic = format!(
+                "{}/sensor/currentsong_{instance}/{id}/config",
+                mqtt_config.discovery_prefix
+            );
+            connection.publish(Message {
+                topic: &topic,
+                payload: config.to_string().as_bytes(),
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl Actor for MqttActor {
+    type MessageType = Option<SongInfo>;
+    fn spawn(self) -> ActorHandle<Self::MessageType> {
+        let (sender, receiver) = flume::unbounded::<Option<SongInfo>>();
+        ActorHandle {
+            sender,
+            thread_handle: thread::spawn(move || {
+                let keep_alive =
+                    Duration::from_secs(self.mqtt_config().keep_alive_secs.max(2).into());
+                let mut connection: Option<Connection> = None;
+                let mut song: Option<SongInfo> = None;
+                let mut changed = true;
+                let mut retry_at = Instant::now();
+
+                loop {
+                    match receiver.recv_timeout(keep_alive / 2) {
+                        Ok(new_song) => {
+                            song = new_song;
+                            changed = true;
+                        }
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+
+                    if connection.is_none() && Instant::now() >= retry_at {
+                        match self.connect() {
+                            Ok(conn) => {
+                                connection = Some(conn);
+                                changed = true;
+                            }
+                            Err(err) => {
+                                warn!("  | Cannot connect to MQTT broker: {err}");
+                                retry_at = Instant::now() + RECONNECT_DELAY;
+                            }
+                        }
+                    }
+                    let Some(conn) = &mut connection else {
+                        continue;
+                    };
+                    let result = if changed {
+                        self.publish_song(conn, song.as_ref())
+                    } else {
+                        conn.ping()
+                    };
+                    match result {
+                        Ok(()) => changed = false,
+                        Err(err) => {
+                            warn!("  | Lost connection to MQTT broker: {err}");
+                            connection = None;
+                        }
+                    }
+                }
+
+                if let Some(mut conn) = connection {
+                    let availability = self.topic("availability");
+                    let _ = self.publish_song(&mut conn, None);
+                    let _ = conn.publish(Message {
+                        topic: &availability,
+                        payload: OFFLINE.as_bytes(),
+                    });
+                    conn.disconnect();
+                }
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn encodes_remaining_length_in_variable_bytes() {
+        assert_eq!(packet(0xC0, &[]), [0xC0, 0x00]);
+        assert_eq!(packet(0x30, &[7; 127])[..2], [0x30, 0x7F]);
+        assert_eq!(packet(0x30, &[7; 128])[..3], [0x30, 0x80, 0x01]);
+        assert_eq!(packet(0x30, &[7; 321])[..3], [0x30, 0xC1, 0x02]);
+        assert_eq!(packet(0x30, &[7; 16384])[..4], [0x30, 0x80, 0x80, 0x01]);
+        assert_eq!(packet(0x30, &[7; 321]).len(), 3 + 321);
+    }
+
+    #[test]
+    fn prefixes_strings_with_their_length() {
+        let mut buffer = Vec::new();
+        write_string(&mut buffer, b"MQTT");
+        write_string(&mut buffer, b"");
+        assert_eq!(buffer, [0x00, 0x04, b'M', b'Q', b'T', b'T', 0x00, 0x00]);
+    }
+
+    #[test]
+    fn publishes_retained_messages() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut broker, _) = listener.accept().unwrap();
+
+        let mut connection = Connection { stream };
+        connection
+            .publish(Message {
+                topic: "a/b",
+                payload: b"on",
+            })
+            .unwrap();
+        connection.disconnect();
+
+        let mut received = Vec::new();
+        broker.read_to_end(&mut received).unwrap();
+        assert_eq!(
+            received,
+            [0x31, 0x07, 0x00, 0x03, b'a', b'/', b'b', b'o', b'n', 0xE0, 0x00]
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Error;
+use flume::{Receiver, RecvTimeoutError};
+use url::Url;
+use zbus::{blocking::Connection, zvariant::Value};
+
+use crate::{
+    artwork::Artwork,
+    config::{Config, NotificationConfig},
+    song::SongInfo,
+    Actor, ActorHandle,
+};
+
+/// How often to check whether a notification is due.
+const TICK: Duration = Duration::from_millis(250);
+
+/// How long to wait for the cover art after a song change.
+const ARTWORK_WAIT: Duration = Duration::from_secs(3);
+
+const APP_NAME: &str = "CurrentSong";
+
+/// A notification waiting to be shown.
+struct Pending {
+    song: SongInfo,
+    /// When the song will have played long enough.
+    due: Instant,
+    /// When to stop waiting for the cover art.
+    artwork_deadline: Instant,
+}
+
+/// An actor that shows a desktop notification on song changes,
+/// through the `org.freedesktop.Notifications` service on the session bus.
+pub struct NotificationActor {
+    config: Arc<Config>,
+    /// Cover art written by the artwork actor, if it is enabled.
+    artwork: Option<Receiver<Artwork>>,
+    connection: Option<Connection>,
+    /// ID of the last notification, which the next one replaces.
+    last_id: u32,
+}
+
+impl NotificationActor {
+    pub fn new(artwork: Option<Receiver<Artwork>>, config: Arc<Config>) -> Self {
+        Self {
+            config,
+            artwork,
+            connection: None,
+            last_id: 0,
+        }
+    }
+
+    fn notification_config(&self) -> &NotificationConfig {
+        self.config.notifications()
+    }
+
+    /// Shows the notification, replacing the previous one if it is still visible.
+    fn notify(&mut self, song: &SongInfo, artwork_path: Option<&Path>) -> Result<(), Error> {
+        let notification_config = self.config.notifications();
+        let summary = song.format(&notification_config.summary);
+        let body = song.format(&notification_config.body);
+        let mut hints: HashMap<&str, Value> = HashMap::new();
+        hints.insert("category", Value::from("x-currentsong.song"));
+        let artwork_uri = artwork_path.and_then(|path| Url::from_file_path(path).ok());
+        if let Some(uri) = &artwork_uri {
+            hints.insert("image-path", Value::from(uri.as_str()));
+        }
+
+        if self.connection.is_none() {
+            self.connection = Some(Connection::session()?);
+        }
+        let connection = self.connection.as_ref().unwrap();
+        let reply = connection.call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.Notifications"),
+            "Notify",
+            &(
+                APP_NAME,
+                self.last_id,
+                "audio-x-generic",
+                summary,
+                body,
+                Vec::<&str>::new(),
+                hints,
+                notification_config.timeout_ms,
+            ),
+        );
+        match reply.and_then(|reply| reply.body().deserialize::<u32>()) {
+            Ok(id) => {
+                self.last_id = id;
+                Ok(())
+            }
+            Err(err) => {
+                // The bus may have gone away, connect again next time
+                self.connection = None;
+                Err(err.into())
+            }
+        }
+    }
+}
+
+impl Actor for NotificationActor {
+    type MessageType = Option<SongInfo>;
+    fn spawn(mut self) -> ActorHandle<Self::MessageType> {
+        let (sender, receiver) = flume::unbounded::<Option<SongInfo>>();
+        ActorHandle {
+            sender,
+            thread_handle: thread::spawn(move || {
+                let delay = Duration::from_secs(self.notification_config().delay_secs);
+                let mut pending: Option<Pending> = None;
+                let mut artwork: Option<Artwork> = None;
+
+                loop {
+                    match receiver.recv_timeout(TICK) {
+                        Ok(song) => {
+                            let now = Instant::now();
+                            pending = song.map(|song| Pending {
+                                song,
+                                due: now + delay,
+                                artwork_deadline: now + ARTWORK_WAIT,
+                            });
+                        }
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                    if let Some(latest) = self.artwork.as_ref().and_then(|a| a.drain().last()) {
+                        artwork = Some(latest);
+                    }
+
+                    let Some(next) = &pending else {
+                        continue;
+                    };
+                    let now = Instant::now();
+                    if now < next.due {
+                        continue;
+                    }
+                    // The cover art is written by another actor, so wait until it says it is done
+                    let artwork_ready = artwork
+                        .as_ref()
+                        .filter(|artwork| artwork.song.as_ref() == Some(&next.song));
+                    if self.artwork.is_some()
+                        && artwork_ready.is_none()
+                        && now < next.artwork_deadline
+                    {
+                        continue;
+                    }
+
+                    let next = pending.take().unwrap();
+                    // Old cover art is better left out than shown with the wrong song
+                    let artwork_path = artwork_ready
+                        .filter(|artwork| !artwork.placeholder)
+                        .map(|artwork| artwork.path.clone());
+                    if let Err(err) = self.notify(&next.song, artwork_path.as_deref()) {
+                        warn!("  | Cannot show a notification: {err}");
+                    }
+                }
+            }),
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use std::{
+    fmt,
+    io::ErrorKind,
+    net::{TcpStream, ToSocketAddrs},
+    path::Path,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Error};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use flume::{Receiver, RecvTimeoutError};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tungstenite::{Message, WebSocket};
+use url::Url;
+
+use crate::{
+    artwork::Artwork,
+    config::{Config, ObsConfig},
+    song::SongInfo,
+    Actor, ActorHandle,
+};
+
+/// How often to check on the connection.
+const TICK: Duration = Duration::from_millis(250);
+
+/// Delay before reconnecting after the connection is lost.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// How long to wait for OBS, so that an unreachable one does not hold up the actor.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+const OP_HELLO: u64 = 0;
+const OP_IDENTIFY: u64 = 1;
+const OP_IDENTIFIED: u64 = 2;
+const OP_REQUEST: u64 = 6;
+const OP_REQUEST_RESPONSE: u64 = 7;
+
+/// OBS could not carry out a request, e.g. because a source does not exist.
+/// Unlike other errors, this does not mean the connection is lost.
+#[derive(Debug)]
+struct RequestFailed(String);
+
+impl fmt::Display for RequestFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for RequestFailed {}
+
+/// A connection to obs-websocket, speaking version 5 of its protocol.
+struct Connection {
+    socket: WebSocket<TcpStream>,
+    next_request_id: u64,
+}
+
+impl Connection {
+    fn open(config: &ObsConfig) -> Result<Connection, Error> {
+        let url = Url::parse(&config.url)?;
+        let host = url.host_str().ok_or_else(|| anyhow!("no host in {url}"))?;
+        let port = url.port().unwrap_or(4455);
+        let address = (host, port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("cannot resolve {host}"))?;
+        let stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+        stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CONNECT_TIMEOUT))?;
+        let (socket, _) = tungstenite::client(url.as_str(), stream)
+            .map_err(|err| anyhow!("WebSocket handshake failed: {err}"))?;
+        let mut connection = Connection {
+            socket,
+            next_request_id: 0,
+        };
+
+        let hello = connection.receive()?;
+        if hello["op"] != OP_HELLO {
+            return Err(anyhow!("expected Hello, got {hello}"));
+        }
+        let mut identify = json!({ "rpcVersion": 1, "eventSubscriptions": 0 });
+        let auth = &hello["d"]["authentication"];
+        if auth.is_object() {
+            let password = config
+                .password
+                .as_deref()
+                .ok_or_else(|| anyhow!("OBS asks for a password, but none is configured"))?;
+            let challenge = auth["challenge"].as_str().unwrap_or_default();
+            let salt = auth["salt"].as_str().unwrap_or_default();
+            identify["authentication"] = json!(authentication(password, salt, challenge));
+        }
+        connection.send(OP_IDENTIFY, identify)?;
+
+        let identified = connection.receive()?;
+        if identified["op"] != OP_IDENTIFIED {
+            return Err(anyhow!("authentication failed"));
+        }
+        Ok(connection)
+    }
+
+    fn send(&mut self, op: u64, data: Value) -> Result<(), Error> {
+        let message = json!({ "op": op, "d": data });
+        self.socket.send(Message::Text(message.to_string()))?;
+        Ok(())
+    }
+
+    /// Reads the next JSON message, skipping control frames.
+    fn receive(&mut self) -> Result<Value, Error> {
+        loop {
+            match self.socket.read()? {
+                Message::Text(text) => return Ok(serde_json::from_str(&text)?),
+                Message::Close(frame) => {
+                    let reason = frame.map(|f| f.reason.to_string()).unwrap_or_default();
+                    return Err(anyhow!("closed by OBS: {reason}"));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Sends a request and waits for its response.
+    fn request(&mut self, request_type: &str, request_data: Value) -> Result<Value, Error> {
+        self.next_request_id += 1;
+        let request_id = self.next_request_id.to_string();
+        self.send(
+            OP_REQUEST,
+            json!({
+                "requestType": request_type,
+                "requestId": request_id,
+                "requestData": request_data,
+            }),
+        )?;
+        loop {
+            let message = self.receive()?;
+            if message["op"] != OP_REQUEST_RESPONSE || message["d"]["requestId"] != request_id {
+                continue;
+            }
+            let status = &message["d"]["requestStatus"];
+            if status["result"] != true {
+                return Err(RequestFailed(format!(
+                    "{request_type} failed with code {}: {}",
+                    status["code"],
+                    status["comment"].as_str().unwrap_or_default()
+                ))
+                .into());
+            }
+            return Ok(message["d"]["responseData"].clone());
+        }
+    }
+
+    /// Answers pings and notices a closed connection, without waiting for messages.
+    fn poll(&mut self) -> Result<(), Error> {
+        self.socket
+            .get_mut()
+            .set_read_timeout(Some(Duration::from_millis(1)))?;
+        let result = loop {
+            match self.socket.read() {
+                Ok(Message::Close(_)) => break Err(anyhow!("closed by OBS")),
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(err))
+                    if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                {
+                    break Ok(())
+                }
+                Err(err) => break Err(err.into()),
+            }
+        };
+        self.socket
+            .get_mut()
+            .set_read_timeout(Some(Duration::from_secs(5)))?;
+        result
+    }
+}
+
+/// Computes the response to an authentication challenge:
+/// `base64(sha256(base64(sha256(password + salt)) + challenge))`.
+fn authentication(password: &str, salt: &str, challenge: &str) -> String {
+    let secret = BASE64.encode(Sha256::digest(format!("{password}{salt}")));
+    BASE64.encode(Sha256::digest(format!("{secret}{challenge}")))
+}
+
+/// An actor that updates sources in OBS directly over obs-websocket:
+/// sets the text of Text sources, points an Image source at the cover art
+/// and shows a scene item only while something is playing.
+pub struct ObsActor {
+    config: Arc<Config>,
+    /// Cover art written by the artwork actor, if it is enabled.
+    artwork: Option<Receiver<Artwork>>,
+}
+
+impl ObsActor {
+    pub fn new(artwork: Option<Receiver<Artwork>>, config: Arc<Config>) -> Self {
+        Self { config, artwork }
+    }
+
+    fn obs_config(&self) -> &ObsConfig {
+        self.config.obs()
+    }
+
+    fn update_song(
+        &self,
+        connection: &mut Connection,
+        song: Option<&SongInfo>,
+    ) -> Result<(), Error> {
+        for source in &self.obs_config().text_sources {
+            let text = match song {
+                Some(song) => {
+                    let template = source
+                        .template
+                        .as_deref()
+                        .unwrap_or(self.config.song_format());
+                    song.format(template)
+                }
+                None => source.empty_text.clone(),
+            };
+            connection.request(
+                "SetInputSettings",
+                json!({ "inputName": source.name, "inputSettings": { "text": text } }),
+            )?;
+        }
+
+        if let Some(item) = &self.obs_config().now_playing_item {
+            let response = connection.request(
+                "GetSceneItemId",
+                json!({ "sceneName": item.scene, "sourceName": item.source }),
+            )?;
+            connection.request(
+                "SetSceneItemEnabled",
+                json!({
+                    "sceneName": item.scene,
+                    "sceneItemId": response["sceneItemId"],
+                    "sceneItemEnabled": song.is_some(),
+                }),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Points the Image source at the cover art file, which makes OBS load it again.
+    fn update_artwork(&self, connection: &mut Connection, path: &Path) -> Result<(), Error> {
+        let Some(source) = &self.obs_config().image_source else {
+            return Ok(());
+        };
+        connection.request(
+            "SetInputSettings",
+            json!({ "inputName": source, "inputSettings": { "file": path } }),
+        )?;
+        Ok(())
+    }
+}
+
+impl Actor for ObsActor {
+    type MessageType = Option<SongInfo>;
+    fn spawn(self) -> ActorHandle<Self::MessageType> {
+        let (sender, receiver) = flume::unbounded::<Option<SongInfo>>();
+        ActorHandle {
+            sender,
+            thread_handle: thread::spawn(move || {
+                if self.obs_config().image_source.is_some() && self.artwork.is_none() {
+                    warn!("  | OBS image source is set, but the artwork output is disabled");
+                }
+
+                let mut connection: Option<Connection> = None;
+                let mut song: Option<SongInfo> = None;
+                let mut song_changed = false;
+                let mut retry_at = Instant::now();
+                let mut artwork: Option<Artwork> = None;
+                let mut artwork_changed = false;
+
+                loop {
+                    match receiver.recv_timeout(TICK) {
+                        Ok(new_song) => {
+                            song = new_song;
+                            song_changed = true;
+                        }
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+
+                    // The cover art is written by another actor, which says when it is done
+                    if let Some(latest) = self.artwork.as_ref().and_then(|a| a.drain().last()) {
+                        artwork = Some(latest);
+                        artwork_changed = true;
+                    }
+
+                    if connection.is_none() && Instant::now() >= retry_at {
+                        match Connection::open(self.obs_config()) {
+                            Ok(conn) => {
+                                connection = Some(conn);
+                                song_changed = true;
+                                artwork_changed = artwork.is_some();
+                            }
+                            Err(err) => {
+                                warn!("  | Cannot connect to OBS: {err}");
+                                retry_at = Instant::now() + RECONNECT_DELAY;
+                            }
+                        }
+                    }
+                    let Some(conn) = &mut connection else {
+                        continue;
+                    };
+
+                    let mut result = conn.poll();
+                    if result.is_ok() && song_changed {
+                        result = self.update_song(conn, song.as_ref());
+                        song_changed = false;
+                    }
+                    if result.is_ok() && artwork_changed {
+                        // Art for a song that was already replaced is about to be overwritten,
+                        // and art may be written before this actor hears of the song
+                        if let Some(current) = artwork.as_ref().filter(|a| a.song == song) {
+                            result = self.update_artwork(conn, &current.path);
+                            artwork_changed = false;
+                        }
+                    }
+                    if let Err(err) = result {
+                        warn!("  | OBS update failed: {err}");
+                        if !err.is::<RequestFailed>() {
+                            connection = None;
+                            song_changed = true;
+                            retry_at = Instant::now() + RECONNECT_DELAY;
+                        }
+                    }
+                }
+
+                if let Some(mut conn) = connection {
+                    let _ = self.update_song(&mut conn, None);
+                    let _ = conn.socket.close(None);
+                    let _ = conn.socket.flush();
+                }
+            }),
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Types shared with plugins across the C ABI.
+//!
+//! A plugin is a dynamic library exporting two functions:
+//!
+//! - `extern "C" fn currentsong_plugin_abi_version() -> u32`, which must return
+//!   [ABI_VERSION] the plugin was built against,
+//! - `extern "C" fn currentsong_plugin_declare() -> *const PluginDeclaration`,
+//!   which is only called if the versions match. The declaration and everything
+//!   it points to must stay valid for as long as the library is loaded.
+//!
+//! All strings are NUL-terminated UTF-8.
+
+use std::ffi::{c_char, c_void};
+
+/// Version of the plugin ABI described in this module.
+/// Bump it on every change to the types below.
+pub const ABI_VERSION: u32 = 1;
+
+pub const ABI_VERSION_SYMBOL: &[u8] = b"currentsong_plugin_abi_version\0";
+pub const DECLARE_SYMBOL: &[u8] = b"currentsong_plugin_declare\0";
+
+pub type AbiVersionFn = unsafe extern "C" fn() -> u32;
+pub type DeclareFn = unsafe extern "C" fn() -> *const PluginDeclaration;
+
+/// Song information passed between the application and plugins.
+/// Strings may be null, which is treated as empty.
+#[repr(C)]
+pub struct CSongInfo {
+    pub artist: *const c_char,
+    pub title: *const c_char,
+}
+
+/// Describes what a plugin provides.
+#[repr(C)]
+pub struct PluginDeclaration {
+    /// Human-readable name of the plugin.
+    pub name: *const c_char,
+    /// Version of the plugin itself, for diagnostics.
+    pub version: *const c_char,
+    pub drivers: *const DriverVTable,
+    pub driver_count: usize,
+    pub outputs: *const OutputVTable,
+    pub output_count: usize,
+}
+
+/// A driver implementation. Selected by setting `driver` in the config to its name.
+#[repr(C)]
+pub struct DriverVTable {
+    pub name: *const c_char,
+    /// Creates a driver instance. May return null on failure.
+    pub create: unsafe extern "C" fn() -> *mut c_void,
+    /// Fills `out` and returns true if a song is playing.
+    /// Strings written to `out` must stay valid until the next call.
+    pub fetch: unsafe extern "C" fn(instance: *mut c_void, out: *mut CSongInfo) -> bool,
+    pub destroy: unsafe extern "C" fn(instance: *mut c_void),
+}
+
+/// An output implementation. Every output of a loaded plugin is enabled.
+#[repr(C)]
+pub struct OutputVTable {
+    pub name: *const c_char,
+    /// Creates an output instance. May return null on failure.
+    pub create: unsafe extern "C" fn() -> *mut c_void,
+    /// Called on every song change. `song` is null when nothing is playing
+    /// and is only valid for the duration of the call.
+    pub update: unsafe extern "C" fn(instance: *mut c_void, song: *const CSongInfo),
+    pub destroy: unsafe extern "C" fn(instance: *mut c_void),
+}
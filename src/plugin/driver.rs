@@ -0,0 +1,56 @@
+use std::{ffi::c_void, ptr, sync::Arc};
+
+use libloading::Library;
+
+use crate::{driver::Driver, song::SongInfo};
+
+use super::{
+    abi::{CSongInfo, DriverVTable},
+    c_str,
+};
+
+/// A [Driver] implemented by a plugin.
+pub struct PluginDriver {
+    vtable: &'static DriverVTable,
+    instance: *mut c_void,
+    _library: Arc<Library>,
+}
+
+impl PluginDriver {
+    pub fn new(library: Arc<Library>, vtable: &'static DriverVTable) -> Option<PluginDriver> {
+        let instance = unsafe { (vtable.create)() };
+        if instance.is_null() {
+            return None;
+        }
+        Some(PluginDriver {
+            vtable,
+            instance,
+            _library: library,
+        })
+    }
+}
+
+impl Driver for PluginDriver {
+    fn fetch_song_info(&mut self) -> Option<SongInfo> {
+        let mut out = CSongInfo {
+            artist: ptr::null(),
+            title: ptr::null(),
+        };
+        unsafe {
+            if !(self.vtable.fetch)(self.instance, &mut out) {
+                return None;
+            }
+            Some(SongInfo {
+                artist: c_str(out.artist),
+                title: c_str(out.title),
+                ..Default::default()
+            })
+        }
+    }
+}
+
+impl Drop for PluginDriver {
+    fn drop(&mut self) {
+        unsafe { (self.vtable.destroy)(self.instance) };
+    }
+}
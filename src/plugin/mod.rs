@@ -0,0 +1,166 @@
+use std::{
+    ffi::{c_char, CStr},
+    path::Path,
+    slice,
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Error};
+use libloading::{library_filename, Library};
+
+use crate::driver::Driver;
+
+use self::{
+    abi::{AbiVersionFn, DeclareFn, DriverVTable, OutputVTable, ABI_VERSION},
+    driver::PluginDriver,
+    output::PluginOutputActor,
+};
+
+pub mod abi;
+mod driver;
+mod output;
+
+/// A dynamic library providing additional drivers and outputs.
+pub struct Plugin {
+    name: String,
+    drivers: &'static [DriverVTable],
+    outputs: &'static [OutputVTable],
+    // Must be dropped last, as the vtables above point into it
+    library: Arc<Library>,
+}
+
+impl Plugin {
+    /// Loads a plugin library, rejecting it if it was built for a different ABI version.
+    pub fn load(path: &Path) -> Result<Plugin, Error> {
+        let library = unsafe { Library::new(path)? };
+
+        let abi_version = unsafe {
+            let abi_version = library.get::<AbiVersionFn>(abi::ABI_VERSION_SYMBOL)?;
+            abi_version()
+        };
+        if abi_version != ABI_VERSION {
+            return Err(anyhow!(
+                "built for plugin ABI v{abi_version}, but this version of CurrentSong supports v{ABI_VERSION}"
+            ));
+        }
+
+        let declaration = unsafe {
+            let declare = library.get::<DeclareFn>(abi::DECLARE_SYMBOL)?;
+            declare()
+                .as_ref()
+                .ok_or_else(|| anyhow!("plugin returned no declaration"))?
+        };
+
+        let (name, version, drivers, outputs) = unsafe {
+            (
+                c_str(declaration.name),
+                c_str(declaration.version),
+                slice_or_empty(declaration.drivers, declaration.driver_count),
+                slice_or_empty(declaration.outputs, declaration.output_count),
+            )
+        };
+        info!("  | Loaded plugin {name} {version}");
+
+        Ok(Plugin {
+            name,
+            drivers,
+            outputs,
+            library: Arc::new(library),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Holds all plugins loaded by the application.
+#[derive(Default)]
+pub struct PluginRegistry {
+    plugins: Vec<Plugin>,
+}
+
+impl PluginRegistry {
+    /// Loads the plugins with the provided names from the `plugins` directory.
+    /// Plugins that cannot be loaded are reported and skipped.
+    pub fn load(data_directory: &Path, names: &[String]) -> PluginRegistry {
+        let directory = data_directory.join("plugins");
+        let plugins = names
+            .iter()
+            .filter_map(|name| {
+                let path = directory.join(library_filename(name));
+                match Plugin::load(&path) {
+                    Ok(plugin) => Some(plugin),
+                    Err(err) => {
+                        warn!("  | Cannot load plugin \"{name}\" from {path:?}: {err}");
+                        None
+                    }
+                }
+            })
+            .collect();
+        PluginRegistry { plugins }
+    }
+
+    /// Creates a driver provided by one of the plugins, if any provides one with that name.
+    pub fn create_driver(&self, name: &str) -> Option<Box<dyn Driver>> {
+        self.plugins.iter().find_map(|plugin| {
+            let vtable = plugin
+                .drivers
+                .iter()
+                .find(|d| unsafe { c_str(d.name) } == name)?;
+            match PluginDriver::new(plugin.library.clone(), vtable) {
+                Some(driver) => Some(Box::new(driver) as Box<dyn Driver>),
+                None => {
+                    warn!(
+                        "  | Plugin {} failed to create driver {name}",
+                        plugin.name()
+                    );
+                    None
+                }
+            }
+        })
+    }
+
+    /// Returns the names of drivers provided by the plugins, with the name of the plugin.
+    pub fn driver_names(&self) -> Vec<(String, &str)> {
+        self.plugins
+            .iter()
+            .flat_map(|plugin| {
+                plugin
+                    .drivers
+                    .iter()
+                    .map(|vtable| (unsafe { c_str(vtable.name) }, plugin.name()))
+            })
+            .collect()
+    }
+
+    /// Creates actors for every output of every loaded plugin.
+    pub fn outputs(&self) -> Vec<PluginOutputActor> {
+        self.plugins
+            .iter()
+            .flat_map(|plugin| {
+                plugin
+                    .outputs
+                    .iter()
+                    .map(|vtable| PluginOutputActor::new(plugin.library.clone(), vtable))
+            })
+            .collect()
+    }
+}
+
+/// Copies a C string, treating null as empty.
+unsafe fn c_str(ptr: *const c_char) -> String {
+    if ptr.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(ptr).to_string_lossy().into_owned()
+    }
+}
+
+unsafe fn slice_or_empty<T>(ptr: *const T, len: usize) -> &'static [T] {
+    if ptr.is_null() || len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(ptr, len)
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    ffi::{c_void, CString},
+    ptr,
+    sync::Arc,
+    thread,
+};
+
+use libloading::Library;
+
+use crate::{song::SongInfo, Actor, ActorHandle};
+
+use super::{
+    abi::{CSongInfo, OutputVTable},
+    c_str,
+};
+
+/// An actor forwarding song changes to an output implemented by a plugin.
+pub struct PluginOutputActor {
+    vtable: &'static OutputVTable,
+    library: Arc<Library>,
+}
+
+/// Plugins are required to accept calls from a thread other than the loading one.
+struct SendVTable(&'static OutputVTable);
+
+unsafe impl Send for SendVTable {}
+
+impl SendVTable {
+    fn into_inner(self) -> &'static OutputVTable {
+        self.0
+    }
+}
+
+impl PluginOutputActor {
+    pub fn new(library: Arc<Library>, vtable: &'static OutputVTable) -> Self {
+        Self { vtable, library }
+    }
+
+    pub fn name(&self) -> String {
+        unsafe { c_str(self.vtable.name) }
+    }
+}
+
+impl Actor for PluginOutputActor {
+    type MessageType = Option<SongInfo>;
+    fn spawn(self) -> ActorHandle<Self::MessageType> {
+        let (sender, receiver) = flume::unbounded::<Option<SongInfo>>();
+        let vtable = SendVTable(self.vtable);
+        let library = self.library;
+        ActorHandle {
+            sender,
+            thread_handle: thread::spawn(move || {
+                let vtable = vtable.into_inner();
+                let name = unsafe { c_str(vtable.name) };
+                let instance = unsafe { (vtable.create)() };
+                if instance.is_null() {
+                    warn!("  | Plugin output {name} failed to initialize");
+                    // Keep receiving, so the app can still send updates
+                    for _ in receiver.iter() {}
+                    return;
+                }
+
+                while let Ok(song) = receiver.recv() {
+                    update(vtable, instance, song.as_ref());
+                }
+
+                unsafe { (vtable.destroy)(instance) };
+                drop(library);
+            }),
+        }
+    }
+}
+
+fn update(vtable: &OutputVTable, instance: *mut c_void, song: Option<&SongInfo>) {
+    let Some(song) = song else {
+        unsafe { (vtable.update)(instance, ptr::null()) };
+        return;
+    };
+
+    // Interior NUL bytes cannot be represented, so they are dropped
+    let to_c = |s: &str| CString::new(s.replace('\0', "")).unwrap_or_default();
+    let artist = to_c(&song.artist);
+    let title = to_c(&song.title);
+    let c_song = CSongInfo {
+        artist: artist.as_ptr(),
+        title: title.as_ptr(),
+    };
+    unsafe { (vtable.update)(instance, &c_song) };
+}
@@ -1,5 +1,32 @@
+use std::{
+    process::{Child, ExitStatus},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Error};
+
 #[cfg(target_os = "windows")]
 mod windows;
 
 #[cfg(target_os = "windows")]
-pub use windows::find_main_window_title;
+pub use windows::{find_main_window_title, press_media_key, MediaKey};
+
+/// How often to check whether a running command has exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Waits for a child process to exit, killing it once the timeout passes.
+pub fn wait_timeout(child: &mut Child, timeout: Duration) -> Result<ExitStatus, Error> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(anyhow!("timed out after {} seconds", timeout.as_secs()));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
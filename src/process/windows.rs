@@ -1,6 +1,7 @@
-use std::string::String;
+use std::{mem, string::String};
 use windows_sys::{
     Win32::Foundation::{BOOL, HWND, LPARAM},
+    Win32::UI::Input::KeyboardAndMouse::*,
     Win32::UI::WindowsAndMessaging::*,
 };
 
@@ -46,6 +47,44 @@ pub fn find_main_window_title(pid: u32) -> Option<String> {
     String::from_utf16(&title).ok()
 }
 
+/// A media key, as found on multimedia keyboards.
+pub enum MediaKey {
+    PlayPause,
+    NextTrack,
+    PreviousTrack,
+}
+
+/// Simulates a press of a media key.
+/// The system routes it to the media session that is currently active.
+pub fn press_media_key(key: MediaKey) -> bool {
+    let vk = match key {
+        MediaKey::PlayPause => VK_MEDIA_PLAY_PAUSE,
+        MediaKey::NextTrack => VK_MEDIA_NEXT_TRACK,
+        MediaKey::PreviousTrack => VK_MEDIA_PREV_TRACK,
+    };
+    let input = |flags| INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: vk,
+                wScan: 0,
+                dwFlags: KEYEVENTF_EXTENDEDKEY | flags,
+                time: 0,
+                dwExtraInfo: 0,
+            },
+        },
+    };
+    let inputs = [input(0), input(KEYEVENTF_KEYUP)];
+    let sent = unsafe {
+        SendInput(
+            inputs.len() as u32,
+            inputs.as_ptr(),
+            mem::size_of::<INPUT>() as i32,
+        )
+    };
+    sent == inputs.len() as u32
+}
+
 unsafe extern "system" fn enum_windows_callback(hwnd: HWND, param: LPARAM) -> BOOL {
     let mut pid: u32 = 0;
     GetWindowThreadProcessId(hwnd, &mut pid);
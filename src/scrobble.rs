@@ -0,0 +1,496 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Error};
+use flume::RecvTimeoutError;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use ureq::Agent;
+
+use crate::{
+    config::{Config, LastFmConfig, ListenBrainzConfig},
+    file::write_atomic,
+    http,
+    json::unix_time,
+    song::SongInfo,
+    Actor, ActorHandle,
+};
+
+const QUEUE_FILE_NAME: &str = "scrobble-queue.json";
+
+/// Scrobbles that failed to submit are kept for this long, which is as far back as Last.fm accepts.
+const MAX_QUEUED_AGE_SECS: i64 = 14 * 24 * 60 * 60;
+
+/// How long to wait before submitting queued scrobbles again after a failure.
+/// The delay doubles with every failure in a row, up to [MAX_RETRY_DELAY].
+const MIN_RETRY_DELAY: Duration = Duration::from_secs(60);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// Last.fm error returned when the session key was revoked.
+const LASTFM_INVALID_SESSION: i64 = 9;
+
+/// Songs shorter than this are never scrobbled.
+const MIN_DURATION_SECS: f64 = 30.0;
+
+/// A song is scrobbled once it has played for this long, even if it is not half over yet.
+const MAX_REQUIRED_SECS: f64 = 4.0 * 60.0;
+
+/// A song that was listened to.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Listen {
+    pub artist: String,
+    pub title: String,
+    pub album: Option<String>,
+    /// Length of the song in seconds.
+    pub duration: Option<f64>,
+    /// Unix time the song started playing.
+    pub listened_at: i64,
+}
+
+impl Listen {
+    fn new(song: &SongInfo, listened_at: i64) -> Self {
+        Self {
+            artist: song.artist.clone(),
+            title: song.title.clone(),
+            album: song.album.clone(),
+            duration: song.duration,
+            listened_at,
+        }
+    }
+
+    /// Checks whether the song has been played long enough to be scrobbled:
+    /// for half its length or four minutes, whichever comes first.
+    /// Songs of unknown length must play for the full four minutes.
+    fn is_scrobbleable(&self, played: Duration) -> bool {
+        let required = match self.duration {
+            Some(duration) if duration < MIN_DURATION_SECS => return false,
+            Some(duration) => (duration / 2.0).min(MAX_REQUIRED_SECS),
+            None => MAX_REQUIRED_SECS,
+        };
+        played.as_secs_f64() >= required
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+enum Service {
+    LastFm,
+    ListenBrainz,
+}
+
+/// Why a submission failed.
+enum SubmitError {
+    /// The service could not be reached or asked to try again later.
+    Retry(Error),
+    /// The service rejected the submission, trying again would not help.
+    Rejected(Error),
+}
+
+impl SubmitError {
+    fn from_transport(err: ureq::Error) -> SubmitError {
+        SubmitError::Retry(anyhow!(err))
+    }
+}
+
+trait Scrobbler: Send {
+    fn service(&self) -> Service;
+    fn now_playing(&mut self, listen: &Listen) -> Result<(), SubmitError>;
+    fn scrobble(&mut self, listen: &Listen) -> Result<(), SubmitError>;
+}
+
+/// Submits to the Last.fm API, see <https://www.last.fm/api/scrobbling>.
+struct LastFm {
+    agent: Agent,
+    config: LastFmConfig,
+    session_key: Option<String>,
+}
+
+impl LastFm {
+    /// Calls a signed API method, returning the response document.
+    /// The session key is forgotten if Last.fm says it is no longer valid.
+    fn call(&mut self, method: &str, params: &[(&str, &str)]) -> Result<Value, SubmitError> {
+        let mut params = params.to_vec();
+        params.push(("method", method));
+        params.push(("api_key", &self.config.api_key));
+        params.sort_by_key(|(key, _)| *key);
+
+        let mut signature = String::new();
+        for (key, value) in &params {
+            signature.push_str(key);
+            signature.push_str(value);
+        }
+        signature.push_str(&self.config.api_secret);
+        let signature = format!("{:x}", md5::compute(signature));
+        params.push(("api_sig", &signature));
+        params.push(("format", "json"));
+
+        let response = match self.agent.post(&self.config.api_url).send_form(&params) {
+            Ok(response) => response,
+            Err(ureq::Error::Status(_, response)) => {
+                let body = read_json(response).unwrap_or_default();
+                let code = body["error"].as_i64().unwrap_or_default();
+                let message = body["message"].as_str().unwrap_or("unknown error");
+                let err = anyhow!("{method} failed with error {code}: {message}");
+                if code == LASTFM_INVALID_SESSION {
+                    self.session_key = None;
+                }
+                // Service offline, temporarily unavailable or rate limited
+                return Err(match code {
+                    LASTFM_INVALID_SESSION | 11 | 16 | 29 => SubmitError::Retry(err),
+                    _ => SubmitError::Rejected(err),
+                });
+            }
+            Err(err) => return Err(SubmitError::from_transport(err)),
+        };
+        read_json(response).map_err(SubmitError::Retry)
+    }
+
+    /// Returns the session key, logging in first if needed.
+    fn session_key(&mut self) -> Result<String, SubmitError> {
+        if let Some(session_key) = &self.session_key {
+            return Ok(session_key.clone());
+        }
+        let (username, password) = (self.config.username.clone(), self.config.password.clone());
+        let params = [
+            ("username", username.as_str()),
+            ("password", password.as_str()),
+        ];
+        let response = self.call("auth.getMobileSession", &params)?;
+        let session_key = response["session"]["key"]
+            .as_str()
+            .ok_or_else(|| SubmitError::Retry(anyhow!("Last.fm did not return a session key")))?
+            .to_string();
+        self.session_key = Some(session_key.clone());
+        Ok(session_key)
+    }
+
+    /// Submits a song, logging in again once if the session key was revoked,
+    /// e.g. because the user disconnected the app from their account.
+    fn submit(&mut self, method: &str, listen: &Listen) -> Result<(), SubmitError> {
+        let logged_in = self.session_key.is_some();
+        let result = self.submit_once(method, listen);
+        if result.is_err() && logged_in && self.session_key.is_none() {
+            return self.submit_once(method, listen);
+        }
+        result
+    }
+
+    fn submit_once(&mut self, method: &str, listen: &Listen) -> Result<(), SubmitError> {
+        let session_key = self.session_key()?;
+        let timestamp = listen.listened_at.to_string();
+        let duration = listen.duration.map(|d| (d.round() as u64).to_string());
+        let mut params = vec![
+            ("sk", session_key.as_str()),
+            ("artist", listen.artist.as_str()),
+            ("track", listen.title.as_str()),
+        ];
+        if let Some(album) = &listen.album {
+            params.push(("album", album));
+        }
+        if let Some(duration) = &duration {
+            params.push(("duration", duration));
+        }
+        if method == "track.scrobble" {
+            params.push(("timestamp", &timestamp));
+        }
+        self.call(method, &params).map(|_| ())
+    }
+}
+
+impl Scrobbler for LastFm {
+    fn service(&self) -> Service {
+        Service::LastFm
+    }
+
+    fn now_playing(&mut self, listen: &Listen) -> Result<(), SubmitError> {
+        self.submit("track.updateNowPlaying", listen)
+    }
+
+    fn scrobble(&mut self, listen: &Listen) -> Result<(), SubmitError> {
+        self.submit("track.scrobble", listen)
+    }
+}
+
+/// Submits to the ListenBrainz API, see <https://listenbrainz.readthedocs.io/en/latest/users/api/>.
+struct ListenBrainz {
+    agent: Agent,
+    config: ListenBrainzConfig,
+}
+
+impl ListenBrainz {
+    fn submit(
+        &self,
+        listen_type: &str,
+        listened_at: Option<i64>,
+        listen: &Listen,
+    ) -> Result<(), SubmitError> {
+        let mut additional_info = json!({ "submission_client": "CurrentSong" });
+        if let Some(duration) = listen.duration {
+            additional_info["duration_ms"] = json!((duration * 1000.0).round() as u64);
+        }
+        let mut payload = json!({
+            "track_metadata": {
+                "artist_name": listen.artist,
+                "track_name": listen.title,
+                "release_name": listen.album,
+                "additional_info": additional_info,
+            }
+        });
+        if let Some(listened_at) = listened_at {
+            payload["listened_at"] = json!(listened_at);
+        }
+        let body = json!({ "listen_type": listen_type, "payload": [payload] });
+
+        let url = format!(
+            "{}/1/submit-listens",
+            self.config.api_url.trim_end_matches('/')
+        );
+        let result = self
+            .agent
+            .post(&url)
+            .set("Authorization", &format!("Token {}", self.config.token))
+            .set("Content-Type", "application/json")
+            .send_string(&body.to_string());
+        match result {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(status, response)) => {
+                let body = read_json(response).unwrap_or_default();
+                let message = body["error"].as_str().unwrap_or("unknown error");
+                let err = anyhow!("ListenBrainz returned {status}: {message}");
+                if status == 429 || status >= 500 {
+                    Err(SubmitError::Retry(err))
+                } else {
+                    Err(SubmitError::Rejected(err))
+                }
+            }
+            Err(err) => Err(SubmitError::from_transport(err)),
+        }
+    }
+}
+
+impl Scrobbler for ListenBrainz {
+    fn service(&self) -> Service {
+        Service::ListenBrainz
+    }
+
+    fn now_playing(&mut self, listen: &Listen) -> Result<(), SubmitError> {
+        self.submit("playing_now", None, listen)
+    }
+
+    fn scrobble(&mut self, listen: &Listen) -> Result<(), SubmitError> {
+        self.submit("single", Some(listen.listened_at), listen)
+    }
+}
+
+fn read_json(response: ureq::Response) -> Result<Value, Error> {
+    Ok(serde_json::from_str(&response.into_string()?)?)
+}
+
+/// A scrobble waiting to be submitted again.
+#[derive(Serialize, Deserialize)]
+struct QueuedListen {
+    service: Service,
+    #[serde(flatten)]
+    listen: Listen,
+}
+
+/// Scrobbles that failed to submit, persisted in the data directory
+/// so they survive a restart.
+struct RetryQueue {
+    path: PathBuf,
+    entries: Vec<QueuedListen>,
+}
+
+impl RetryQueue {
+    fn load(data_directory: &Path) -> Self {
+        let path = data_directory.join(QUEUE_FILE_NAME);
+        let entries = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|err| {
+                warn!("  | Cannot read {path:?}, queued scrobbles are lost: {err}");
+                Vec::new()
+            }),
+            Err(err) if err.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(err) => {
+                warn!("  | Cannot read {path:?}: {err}");
+                Vec::new()
+            }
+        };
+        Self { path, entries }
+    }
+
+    fn save(&mut self) {
+        let oldest_allowed = unix_time() as i64 - MAX_QUEUED_AGE_SECS;
+        self.entries
+            .retain(|entry| entry.listen.listened_at >= oldest_allowed);
+        let result = serde_json::to_vec_pretty(&self.entries)
+            .map_err(Error::from)
+            .and_then(|json| Ok(write_atomic(&self.path, &json)?));
+        if let Err(err) = result {
+            warn!("  | Cannot save {:?}: {err}", &self.path);
+        }
+    }
+}
+
+/// An actor that reports the song to Last.fm and ListenBrainz as "now playing",
+/// and scrobbles it once it has played long enough.
+pub struct ScrobbleActor {
+    scrobblers: Vec<Box<dyn Scrobbler>>,
+    queue: RetryQueue,
+    /// When to submit queued scrobbles again, if a service was unavailable.
+    retry_at: Option<Instant>,
+    retry_delay: Duration,
+}
+
+impl ScrobbleActor {
+    /// Creates the actor, or returns `None` if no service is enabled.
+    pub fn new(data_directory: &Path, config: Arc<Config>) -> Option<Self> {
+        let scrobble_config = config.scrobble();
+        let agent = http::agent(Duration::from_secs(scrobble_config.timeout_secs));
+        let mut scrobblers: Vec<Box<dyn Scrobbler>> = Vec::new();
+        if scrobble_config.lastfm.enabled {
+            scrobblers.push(Box::new(LastFm {
+                agent: agent.clone(),
+                config: scrobble_config.lastfm.clone(),
+                session_key: None,
+            }));
+        }
+        if scrobble_config.listenbrainz.enabled {
+            scrobblers.push(Box::new(ListenBrainz {
+                agent,
+                config: scrobble_config.listenbrainz.clone(),
+            }));
+        }
+        if scrobblers.is_empty() {
+            return None;
+        }
+        Some(Self {
+            scrobblers,
+            queue: RetryQueue::load(data_directory),
+            retry_at: None,
+            retry_delay: MIN_RETRY_DELAY,
+        })
+    }
+
+    fn now_playing(&mut self, listen: &Listen) {
+        for scrobbler in &mut self.scrobblers {
+            if let Err(SubmitError::Retry(err) | SubmitError::Rejected(err)) =
+                scrobbler.now_playing(listen)
+            {
+                warn!(
+                    "  | Cannot update now playing on {:?}: {err}",
+                    scrobbler.service()
+                );
+            }
+        }
+    }
+
+    fn scrobble(&mut self, listen: &Listen) {
+        for scrobbler in &self.scrobblers {
+            self.queue.entries.push(QueuedListen {
+                service: scrobbler.service(),
+                listen: listen.clone(),
+            });
+        }
+        self.flush();
+    }
+
+    /// Submits queued scrobbles, oldest first.
+    /// A service that fails is not tried again until the next flush,
+    /// which is scheduled with a growing delay.
+    fn flush(&mut self) {
+        self.retry_at = None;
+        if self.queue.entries.is_empty() {
+            return;
+        }
+        let mut unavailable = Vec::new();
+        let mut remaining = Vec::new();
+        for entry in std::mem::take(&mut self.queue.entries) {
+            let scrobbler = self
+                .scrobblers
+                .iter_mut()
+                .find(|scrobbler| scrobbler.service() == entry.service);
+            // Keep scrobbles for disabled services, in case they are enabled again
+            let Some(scrobbler) = scrobbler else {
+                remaining.push(entry);
+                continue;
+            };
+            if unavailable.contains(&entry.service) {
+                remaining.push(entry);
+                continue;
+            }
+            match scrobbler.scrobble(&entry.listen) {
+                Ok(()) => {}
+                Err(SubmitError::Retry(err)) => {
+                    warn!(
+                        "  | Cannot scrobble to {:?}, will retry: {err}",
+                        entry.service
+                    );
+                    unavailable.push(entry.service);
+                    remaining.push(entry);
+                }
+                Err(SubmitError::Rejected(err)) => {
+                    warn!("  | {:?} rejected a scrobble: {err}", entry.service);
+                }
+            }
+        }
+        self.queue.entries = remaining;
+        self.queue.save();
+
+        if unavailable.is_empty() {
+            self.retry_delay = MIN_RETRY_DELAY;
+        } else {
+            self.retry_at = Some(Instant::now() + self.retry_delay);
+            self.retry_delay = (self.retry_delay * 2).min(MAX_RETRY_DELAY);
+        }
+    }
+
+    /// Scrobbles the song that has just stopped playing, if it played long enough.
+    fn finish(&mut self, current: Option<(Listen, Instant)>) {
+        if let Some((listen, started)) = current {
+            if listen.is_scrobbleable(started.elapsed()) {
+                self.scrobble(&listen);
+            }
+        }
+    }
+}
+
+impl Actor for ScrobbleActor {
+    type MessageType = Option<SongInfo>;
+    fn spawn(mut self) -> ActorHandle<Self::MessageType> {
+        let (sender, receiver) = flume::unbounded::<Option<SongInfo>>();
+        ActorHandle {
+            sender,
+            thread_handle: thread::spawn(move || {
+                self.flush();
+                let mut current: Option<(Listen, Instant)> = None;
+                loop {
+                    let received = match self.retry_at {
+                        Some(retry_at) => receiver.recv_deadline(retry_at),
+                        None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                    };
+                    let song = match received {
+                        Ok(song) => song,
+                        Err(RecvTimeoutError::Timeout) => {
+                            self.flush();
+                            continue;
+                        }
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    };
+                    self.finish(current.take());
+                    if let Some(song) = song.filter(|song| !song.custom) {
+                        let listen = Listen::new(&song, unix_time() as i64);
+                        self.now_playing(&listen);
+                        current = Some((listen, Instant::now()));
+                    }
+                }
+                self.finish(current);
+            }),
+        }
+    }
+}
@@ -0,0 +1,362 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use anyhow::{anyhow, Error};
+use flume::Sender;
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Response};
+use url::Url;
+
+use crate::{
+    app::LifecycleEvent,
+    config::Config,
+    driver::PlayerCommand,
+    history::{History, SessionMarker},
+    http,
+    json::{unix_time, NowPlaying},
+    song::SongInfo,
+    song_override::{parse_duration, SongOverride},
+    tracklist::{self, Format, Selection},
+    Actor, ActorHandle,
+};
+
+/// Header that requests changing anything must carry, with the configured token if there is one.
+/// Browsers only send custom headers to another origin after asking in a preflight request,
+/// which this server never allows, and the `Host` of such requests must name this machine,
+/// so web pages cannot send commands on a visitor's behalf, even through DNS rebinding.
+const TOKEN_HEADER: &str = "X-CurrentSong-Token";
+
+/// An actor serving the current song and the song history over local HTTP.
+///
+/// - `GET /now` returns the same document as `nowplaying.json`,
+/// - `GET /history/last?count=10` returns the last songs played,
+/// - `GET /history/sessions?count=10` returns the last sessions,
+/// - `GET /history/session?id=1` returns songs played in a session,
+/// - `GET /history/top-artists?from=2024-05-01&to=2024-06-01&count=10`
+///   returns the most played artists in a time range,
+/// - `GET /history/tracklist?format=youtube&session=1` (or `from`, `to` and `start`)
+///   exports a tracklist,
+/// - `POST /session/start` and `POST /session/end` mark session boundaries,
+/// - `POST /player/next` (or `play`, `pause`, `toggle`, `previous`, `seek?seconds=90`
+///   and `volume?level=0.5`) controls the media player.
+///
+/// `POST` requests must carry the [TOKEN_HEADER] header and be addressed to `localhost`.
+pub struct ServerActor {
+    config: Arc<Config>,
+    data_directory: PathBuf,
+    lifecycle_sender: Sender<LifecycleEvent>,
+}
+
+enum ApiError {
+    NotFound,
+    BadRequest(String),
+    Forbidden(String),
+    Internal(Error),
+}
+
+impl From<Error> for ApiError {
+    fn from(err: Error) -> Self {
+        ApiError::Internal(err)
+    }
+}
+
+/// A successful response body.
+struct Reply {
+    body: String,
+    content_type: &'static str,
+}
+
+type ApiResult = Result<Reply, ApiError>;
+
+impl ServerActor {
+    pub fn new(
+        data_directory: PathBuf,
+        lifecycle_sender: Sender<LifecycleEvent>,
+        config: Arc<Config>,
+    ) -> Self {
+        Self {
+            config,
+            data_directory,
+            lifecycle_sender,
+        }
+    }
+}
+
+impl Actor for ServerActor {
+    type MessageType = Option<SongInfo>;
+    fn spawn(self) -> ActorHandle<Self::MessageType> {
+        let (sender, receiver) = flume::unbounded::<Option<SongInfo>>();
+        ActorHandle {
+            sender,
+            thread_handle: thread::spawn(move || {
+                let port = self.config.server().port;
+                let current_song = Arc::new(Mutex::new(None));
+
+                let server = match http::listen(port) {
+                    Ok(server) => Some(Arc::new(server)),
+                    Err(err) => {
+                        warn!("  | Cannot start HTTP server on port {port}: {err:?}");
+                        None
+                    }
+                };
+
+                if let Some(server) = &server {
+                    let server = server.clone();
+                    let current_song = current_song.clone();
+                    let lifecycle_sender = self.lifecycle_sender.clone();
+                    let token = self.config.server().token.clone();
+                    let port = self.config.server().port;
+                    let history = if self.config.history().enabled {
+                        History::open(&self.data_directory)
+                            .map_err(|err| warn!("  | Cannot open song history: {err}"))
+                            .ok()
+                    } else {
+                        None
+                    };
+                    thread::spawn(move || {
+                        while let Ok(request) = server.recv() {
+                            let context = Context {
+                                current_song: &current_song,
+                                history: history.as_ref(),
+                                lifecycle_sender: &lifecycle_sender,
+                                token: token.as_deref(),
+                                port,
+                            };
+                            handle_request(request, &context);
+                        }
+                    });
+                }
+
+                while let Ok(song) = receiver.recv() {
+                    *current_song.lock().unwrap() = song;
+                }
+
+                if let Some(server) = server {
+                    server.unblock();
+                }
+            }),
+        }
+    }
+}
+
+/// State shared by the request handlers.
+struct Context<'a> {
+    current_song: &'a Mutex<Option<SongInfo>>,
+    history: Option<&'a History>,
+    lifecycle_sender: &'a Sender<LifecycleEvent>,
+    /// Token that changing requests must carry, if one is configured.
+    token: Option<&'a str>,
+    /// Port the server listens on, which changing requests must be addressed to.
+    port: u16,
+}
+
+fn handle_request(request: Request, context: &Context) {
+    let result = match Url::parse(&format!("http://localhost{}", request.url())) {
+        Ok(url) => {
+            let query = url.query_pairs().into_owned().collect::<HashMap<_, _>>();
+            route(
+                request.method(),
+                url.path(),
+                &query,
+                request.headers(),
+                context,
+            )
+        }
+        Err(_) => Err(ApiError::BadRequest("malformed URL".into())),
+    };
+
+    let (status, reply) = match result {
+        Ok(reply) => (200, reply),
+        Err(ApiError::NotFound) => (404, error_reply("not found")),
+        Err(ApiError::BadRequest(message)) => (400, error_reply(&message)),
+        Err(ApiError::Forbidden(message)) => (403, error_reply(&message)),
+        Err(ApiError::Internal(err)) => {
+            warn!("  | HTTP server error: {err}");
+            (500, error_reply("internal error"))
+        }
+    };
+
+    let mut response = Response::from_string(reply.body)
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", reply.content_type).unwrap());
+    // Overlays in a browser source may read the song, but only reading is open to every page
+    if *request.method() == Method::Get {
+        response.add_header(Header::from_bytes("Access-Control-Allow-Origin", "*").unwrap());
+    }
+    let _ = request.respond(response);
+}
+
+fn route(
+    method: &Method,
+    path: &str,
+    query: &HashMap<String, String>,
+    headers: &[Header],
+    context: &Context,
+) -> ApiResult {
+    if *method == Method::Post {
+        authorize(headers, context)?;
+        let event = match path {
+            "/session/start" => LifecycleEvent::Session(SessionMarker::Start),
+            "/session/end" => LifecycleEvent::Session(SessionMarker::End),
+            "/override" => LifecycleEvent::Override(parse_override(query)?),
+            _ => match path.strip_prefix("/player/") {
+                Some(command) => LifecycleEvent::Player(parse_player_command(command, query)?),
+                None => return Err(ApiError::NotFound),
+            },
+        };
+        context
+            .lifecycle_sender
+            .send(event)
+            .map_err(|err| ApiError::Internal(anyhow!(err)))?;
+        return to_json(&serde_json::json!({ "ok": true }));
+    }
+
+    if *method != Method::Get {
+        return Err(ApiError::NotFound);
+    }
+
+    if path == "/now" {
+        let song = context.current_song.lock().unwrap();
+        return to_json(&NowPlaying::new(song.as_ref()));
+    }
+
+    let Some(history) = context.history else {
+        return Err(ApiError::NotFound);
+    };
+    let count = parse_param(query, "count")?.unwrap_or(10);
+    match path {
+        "/history/last" => to_json(&history.last(count)?),
+        "/history/sessions" => to_json(&history.sessions(count)?),
+        "/history/session" => {
+            let id = parse_param(query, "id")?
+                .ok_or_else(|| ApiError::BadRequest("missing parameter: id".into()))?;
+            to_json(&history.session(id)?)
+        }
+        "/history/top-artists" => {
+            let from = parse_time(history, query, "from")?.unwrap_or(0);
+            let to = parse_time(history, query, "to")?.unwrap_or(unix_time() as i64 + 1);
+            to_json(&history.top_artists(from, to, count)?)
+        }
+        "/history/tracklist" => {
+            let format = match query.get("format") {
+                Some(format) => format
+                    .parse::<Format>()
+                    .map_err(|err| ApiError::BadRequest(err.to_string()))?,
+                None => Format::YouTube,
+            };
+            let selection = Selection {
+                session: parse_param(query, "session")?,
+                from: parse_time(history, query, "from")?,
+                to: parse_time(history, query, "to")?,
+                stream_start: parse_time(history, query, "start")?,
+            };
+            let (plays, stream_start) = tracklist::select(history, &selection)
+                .map_err(|err| ApiError::BadRequest(err.to_string()))?;
+            Ok(Reply {
+                body: tracklist::export(&plays, format, stream_start),
+                content_type: format.content_type(),
+            })
+        }
+        _ => Err(ApiError::NotFound),
+    }
+}
+
+/// Checks that a changing request is addressed to this machine
+/// and carries the token in the [TOKEN_HEADER] header.
+fn authorize(headers: &[Header], context: &Context) -> Result<(), ApiError> {
+    let host = http::header(headers, "Host").unwrap_or_default();
+    if !http::is_local_host(host, context.port) {
+        return Err(ApiError::Forbidden(format!("unexpected host: {host}")));
+    }
+    match (http::header(headers, TOKEN_HEADER), context.token) {
+        (None, _) => Err(ApiError::Forbidden(format!(
+            "missing {TOKEN_HEADER} header"
+        ))),
+        (Some(token), Some(expected))
+            if !http::constant_time_eq(token.as_bytes(), expected.as_bytes()) =>
+        {
+            Err(ApiError::Forbidden("wrong token".into()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Reads custom text from the `text` parameter, shown for the duration in `for` if set.
+/// Without text, the song is shown again.
+fn parse_override(query: &HashMap<String, String>) -> Result<Option<SongOverride>, ApiError> {
+    let Some(text) = query.get("text").filter(|text| !text.trim().is_empty()) else {
+        return Ok(None);
+    };
+    let duration = query
+        .get("for")
+        .map(|duration| parse_duration(duration))
+        .transpose()
+        .map_err(|err| ApiError::BadRequest(err.to_string()))?;
+    SongOverride::new(text.trim().to_string(), duration)
+        .map(Some)
+        .map_err(|err| ApiError::BadRequest(err.to_string()))
+}
+
+/// Reads a player command from the path, taking its argument from `seconds` or `level`.
+fn parse_player_command(
+    command: &str,
+    query: &HashMap<String, String>,
+) -> Result<PlayerCommand, ApiError> {
+    let argument = query
+        .get("seconds")
+        .or_else(|| query.get("level"))
+        .map(String::as_str)
+        .unwrap_or_default();
+    format!("{command} {argument}")
+        .trim()
+        .parse()
+        .map_err(|err: Error| ApiError::BadRequest(err.to_string()))
+}
+
+fn parse_param<T: std::str::FromStr>(
+    query: &HashMap<String, String>,
+    name: &str,
+) -> Result<Option<T>, ApiError> {
+    query
+        .get(name)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| ApiError::BadRequest(format!("invalid parameter: {name}")))
+        })
+        .transpose()
+}
+
+/// Parses a parameter holding either Unix time or a date, such as `2024-05-01`.
+fn parse_time(
+    history: &History,
+    query: &HashMap<String, String>,
+    name: &str,
+) -> Result<Option<i64>, ApiError> {
+    let Some(value) = query.get(name) else {
+        return Ok(None);
+    };
+    match history.parse_time(value)? {
+        Some(time) => Ok(Some(time)),
+        None => Err(ApiError::BadRequest(format!("invalid parameter: {name}"))),
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> ApiResult {
+    let body = serde_json::to_string(value).map_err(|err| ApiError::Internal(anyhow!(err)))?;
+    Ok(Reply {
+        body,
+        content_type: "application/json",
+    })
+}
+
+fn error_reply(message: &str) -> Reply {
+    Reply {
+        body: serde_json::json!({ "error": message }).to_string(),
+        content_type: "application/json",
+    }
+}
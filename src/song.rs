@@ -1,5 +1,32 @@
-#[derive(PartialEq, Clone)]
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+#[derive(Serialize, PartialEq, Clone, Default)]
 pub struct SongInfo {
     pub artist: String,
     pub title: String,
+    pub album: Option<String>,
+    /// URL of the album art, if the player exposes one.
+    pub artwork_url: Option<String>,
+    /// Path to the local file being played, if known.
+    pub path: Option<PathBuf>,
+    /// Length of the song in seconds, if the player exposes it.
+    pub duration: Option<f64>,
+    /// Whether this is custom text shown instead of the song, see [SongOverride].
+    /// It is not scrobbled, recorded in the history or announced in chat.
+    ///
+    /// [SongOverride]: crate::song_override::SongOverride
+    #[serde(skip)]
+    pub custom: bool,
+}
+
+impl SongInfo {
+    /// Fills a template's `{artist}`, `{title}` and `{album}` placeholders.
+    pub fn format(&self, template: &str) -> String {
+        template
+            .replace("{artist}", &self.artist)
+            .replace("{title}", &self.title)
+            .replace("{album}", self.album.as_deref().unwrap_or_default())
+    }
 }
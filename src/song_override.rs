@@ -0,0 +1,162 @@
+use std::{fs, io::ErrorKind, path::Path, time::Duration};
+
+use anyhow::{anyhow, Error};
+use serde::{Deserialize, Serialize};
+
+use crate::{file::write_atomic, json::unix_time, song::SongInfo};
+
+const OVERRIDE_FILE_NAME: &str = "override.json";
+
+/// Custom text that outputs show instead of the song, such as "BRB - lofi beats".
+/// It is kept in the data directory, so that it survives restarts.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SongOverride {
+    pub text: String,
+    /// Unix time to show the song again at, in seconds, or `None` to keep the text until cleared.
+    pub expires_at: Option<u64>,
+}
+
+impl SongOverride {
+    /// Fails if the text would expire too far in the future to be represented.
+    pub fn new(text: String, duration: Option<Duration>) -> Result<Self, Error> {
+        let expires_at = duration
+            .map(|duration| {
+                unix_time()
+                    .checked_add(duration.as_secs())
+                    .ok_or_else(|| anyhow!("invalid duration: {}s", duration.as_secs()))
+            })
+            .transpose()?;
+        Ok(Self { text, expires_at })
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= unix_time())
+    }
+
+    /// Makes a song out of the text, split into artist and title at the first " - ".
+    pub fn song(&self) -> SongInfo {
+        let (artist, title) = self.text.split_once(" - ").unwrap_or(("", &self.text));
+        SongInfo {
+            artist: artist.trim().to_string(),
+            title: title.trim().to_string(),
+            custom: true,
+            ..Default::default()
+        }
+    }
+
+    /// Reads the override set before the app was last closed, if it has not expired since.
+    pub fn load(data_directory: &Path) -> Option<SongOverride> {
+        let path = data_directory.join(OVERRIDE_FILE_NAME);
+        let json = match fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(err) if err.kind() == ErrorKind::NotFound => return None,
+            Err(err) => {
+                warn!("  | Cannot read {path:?}: {err}");
+                return None;
+            }
+        };
+        match serde_json::from_str::<SongOverride>(&json) {
+            Ok(song_override) if !song_override.is_expired() => Some(song_override),
+            Ok(_) => None,
+            Err(err) => {
+                warn!("  | Cannot read {path:?}: {err}");
+                None
+            }
+        }
+    }
+
+    /// Saves the override to be restored on the next start, or forgets it if `None`.
+    pub fn save(song_override: Option<&SongOverride>, data_directory: &Path) {
+        let path = data_directory.join(OVERRIDE_FILE_NAME);
+        let result = match song_override {
+            Some(song_override) => serde_json::to_vec_pretty(song_override)
+                .map_err(|err| err.into())
+                .and_then(|json| write_atomic(&path, &json)),
+            None => match fs::remove_file(&path) {
+                Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+                result => result,
+            },
+        };
+        if let Err(err) = result {
+            warn!("  | Cannot save {path:?}: {err}");
+        }
+    }
+}
+
+/// Parses a duration such as `90`, `90s`, `15m` or `2h`. Plain numbers are seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, Error> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => s.split_at(index),
+        None => (s, "s"),
+    };
+    let number = number
+        .parse::<u64>()
+        .map_err(|_| anyhow!("invalid duration: {s}"))?;
+    let seconds = match unit {
+        "s" => Some(number),
+        "m" | "min" => number.checked_mul(60),
+        "h" => number.checked_mul(3600),
+        _ => None,
+    };
+    let seconds = seconds.ok_or_else(|| anyhow!("invalid duration: {s}"))?;
+    Ok(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations_with_units() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration(" 15m ").unwrap(), Duration::from_secs(900));
+        assert_eq!(parse_duration("15min").unwrap(), Duration::from_secs(900));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        for s in ["", "m", "-5", "1.5h", "10d", "5 m"] {
+            assert!(parse_duration(s).is_err(), "{s:?} should not parse");
+        }
+    }
+
+    #[test]
+    fn rejects_durations_that_overflow() {
+        assert!(parse_duration(&format!("{}h", u64::MAX)).is_err());
+        assert!(parse_duration("99999999999999999999").is_err());
+        assert!(SongOverride::new("BRB".to_string(), Some(Duration::from_secs(u64::MAX))).is_err());
+    }
+
+    #[test]
+    fn expires_only_with_a_duration() {
+        let forever = SongOverride::new("BRB".to_string(), None).unwrap();
+        assert_eq!(forever.expires_at, None);
+        assert!(!forever.is_expired());
+
+        let expired = SongOverride::new("BRB".to_string(), Some(Duration::ZERO)).unwrap();
+        assert!(expired.is_expired());
+    }
+
+    #[test]
+    fn splits_text_into_artist_and_title() {
+        let song = SongOverride::new("DJ Set - Live from Home - Part 2".to_string(), None)
+            .unwrap()
+            .song();
+        assert_eq!(song.artist, "DJ Set");
+        assert_eq!(song.title, "Live from Home - Part 2");
+        assert!(song.custom);
+    }
+
+    #[test]
+    fn uses_text_without_separator_as_title() {
+        let song = SongOverride::new("BRB-lofi beats".to_string(), None)
+            .unwrap()
+            .song();
+        assert_eq!(song.artist, "");
+        assert_eq!(song.title, "BRB-lofi beats");
+    }
+}
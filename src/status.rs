@@ -0,0 +1,41 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Serialize, Serializer};
+
+use crate::{song::SongInfo, song_override::SongOverride};
+
+/// A snapshot of what the app is doing, kept up to date by the main loop
+/// for frontends such as the terminal dashboard and the control socket.
+#[derive(Serialize, Clone, Default)]
+pub struct Status {
+    /// Name of the driver in use.
+    pub driver: String,
+    /// The song the driver reported last.
+    pub song: Option<SongInfo>,
+    /// When the driver first reported the current song.
+    #[serde(serialize_with = "unix_time")]
+    pub song_since: Option<SystemTime>,
+    /// When the driver was last asked for the song.
+    #[serde(serialize_with = "unix_time")]
+    pub last_fetch: Option<SystemTime>,
+    /// Whether song changes are held back from outputs.
+    pub paused: bool,
+    /// Text shown by outputs instead of the song, if set.
+    #[serde(rename = "override")]
+    pub song_override: Option<SongOverride>,
+    pub outputs: Vec<OutputStatus>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct OutputStatus {
+    pub name: String,
+    /// Whether the output's thread is still running, i.e. it has not panicked.
+    pub running: bool,
+}
+
+/// Serializes a time as Unix time in seconds, like the other times in the API.
+fn unix_time<S: Serializer>(time: &Option<SystemTime>, serializer: S) -> Result<S::Ok, S::Error> {
+    time.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .serialize(serializer)
+}
@@ -0,0 +1,287 @@
+use std::{fmt::Write, path::Path, str::FromStr};
+
+use anyhow::{anyhow, Error};
+use url::Url;
+
+use crate::{
+    history::{History, Play},
+    json::unix_time,
+};
+
+/// A format a tracklist can be exported to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// `00:12:34 Artist - Title` lines, as understood by YouTube chapters.
+    YouTube,
+    /// A CUE sheet, with tracks indexed relative to the stream start.
+    Cue,
+    /// An extended M3U playlist, encoded as UTF-8.
+    M3u8,
+    /// An XML Shareable Playlist Format document.
+    Xspf,
+}
+
+impl Format {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::YouTube | Format::Cue => "text/plain; charset=utf-8",
+            Format::M3u8 => "audio/x-mpegurl",
+            Format::Xspf => "application/xspf+xml",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "youtube" | "chapters" => Ok(Format::YouTube),
+            "cue" => Ok(Format::Cue),
+            "m3u" | "m3u8" => Ok(Format::M3u8),
+            "xspf" => Ok(Format::Xspf),
+            _ => Err(anyhow!("unknown tracklist format: {s}")),
+        }
+    }
+}
+
+/// Renders songs as a tracklist.
+/// `stream_start` is the Unix time offsets (of chapters, CUE indexes) are relative to.
+pub fn export(plays: &[Play], format: Format, stream_start: i64) -> String {
+    match format {
+        Format::YouTube => youtube(plays, stream_start),
+        Format::Cue => cue(plays, stream_start),
+        Format::M3u8 => m3u8(plays),
+        Format::Xspf => xspf(plays),
+    }
+}
+
+fn offset(play: &Play, stream_start: i64) -> i64 {
+    (play.started_at - stream_start).max(0)
+}
+
+fn youtube(plays: &[Play], stream_start: i64) -> String {
+    let mut out = String::new();
+    // YouTube ignores the chapters unless the first one starts at zero
+    if plays
+        .first()
+        .is_some_and(|play| offset(play, stream_start) > 0)
+    {
+        out.push_str("00:00:00 Intro\n");
+    }
+    for play in plays {
+        let seconds = offset(play, stream_start);
+        let _ = writeln!(
+            out,
+            "{:02}:{:02}:{:02} {} - {}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60,
+            play.artist,
+            play.title
+        );
+    }
+    out
+}
+
+fn cue(plays: &[Play], stream_start: i64) -> String {
+    let mut out = String::new();
+    out.push_str("TITLE \"Tracklist\"\n");
+    out.push_str("FILE \"stream.wav\" WAVE\n");
+    for (index, play) in plays.iter().enumerate() {
+        let seconds = offset(play, stream_start);
+        let _ = writeln!(out, "  TRACK {:02} AUDIO", index + 1);
+        let _ = writeln!(out, "    TITLE \"{}\"", cue_escape(&play.title));
+        let _ = writeln!(out, "    PERFORMER \"{}\"", cue_escape(&play.artist));
+        // Frames (1/75 s) are always zero, as the history has a resolution of one second
+        let _ = writeln!(
+            out,
+            "    INDEX 01 {:02}:{:02}:00",
+            seconds / 60,
+            seconds % 60
+        );
+    }
+    out
+}
+
+fn cue_escape(text: &str) -> String {
+    text.replace('"', "'")
+}
+
+fn m3u8(plays: &[Play]) -> String {
+    let mut out = String::from("#EXTM3U\n");
+    for play in plays {
+        let duration = play.duration.unwrap_or(-1);
+        let _ = writeln!(out, "#EXTINF:{duration},{} - {}", play.artist, play.title);
+        // Players skip entries without a location, so the title is repeated as a comment
+        match &play.path {
+            Some(path) => out.push_str(path),
+            None => {
+                let _ = write!(out, "# {} - {}", play.artist, play.title);
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn xspf(plays: &[Play]) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n");
+    out.push_str("  <trackList>\n");
+    for play in plays {
+        out.push_str("    <track>\n");
+        let location = play
+            .path
+            .as_deref()
+            .and_then(|path| Url::from_file_path(Path::new(path)).ok());
+        if let Some(location) = location {
+            let _ = writeln!(
+                out,
+                "      <location>{}</location>",
+                xml_escape(location.as_str())
+            );
+        }
+        let _ = writeln!(out, "      <creator>{}</creator>", xml_escape(&play.artist));
+        let _ = writeln!(out, "      <title>{}</title>", xml_escape(&play.title));
+        if let Some(album) = &play.album {
+            let _ = writeln!(out, "      <album>{}</album>", xml_escape(album));
+        }
+        if let Some(duration) = play.duration {
+            let _ = writeln!(out, "      <duration>{}</duration>", duration * 1000);
+        }
+        out.push_str("    </track>\n");
+    }
+    out.push_str("  </trackList>\n");
+    out.push_str("</playlist>\n");
+    out
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Which part of the history to export.
+#[derive(Default)]
+pub struct Selection {
+    /// ID of a session to export. Takes precedence over the time range.
+    pub session: Option<i64>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    /// Unix time the stream started. Defaults to the start of the session or the range.
+    pub stream_start: Option<i64>,
+}
+
+/// Looks up the selected songs in the history, along with the stream start time.
+pub fn select(history: &History, selection: &Selection) -> Result<(Vec<Play>, i64), Error> {
+    if let Some(session_id) = selection.session {
+        let session_start = history
+            .session_start(session_id)?
+            .ok_or_else(|| anyhow!("no session with ID {session_id}"))?;
+        let plays = history.session(session_id)?;
+        return Ok((plays, selection.stream_start.unwrap_or(session_start)));
+    }
+
+    let from = selection.from.unwrap_or(0);
+    let to = selection.to.unwrap_or(unix_time() as i64 + 1);
+    let plays = history.between(from, to)?;
+    let stream_start = selection
+        .stream_start
+        .or(selection.from)
+        .or_else(|| plays.first().map(|play| play.started_at))
+        .unwrap_or(from);
+    Ok((plays, stream_start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(started_at: i64, artist: &str, title: &str) -> Play {
+        Play {
+            id: 0,
+            session_id: 1,
+            started_at,
+            ended_at: Some(started_at + 200),
+            duration: Some(200),
+            driver: "mpd".to_string(),
+            artist: artist.to_string(),
+            title: title.to_string(),
+            album: None,
+            path: None,
+        }
+    }
+
+    #[test]
+    fn parses_format_names() {
+        assert_eq!("YouTube".parse::<Format>().unwrap(), Format::YouTube);
+        assert_eq!("chapters".parse::<Format>().unwrap(), Format::YouTube);
+        assert_eq!("m3u".parse::<Format>().unwrap(), Format::M3u8);
+        assert!("pls".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn exports_youtube_chapters_from_zero() {
+        let plays = [play(1030, "A", "One"), play(4700, "B", "Two")];
+        assert_eq!(
+            export(&plays, Format::YouTube, 1000),
+            "00:00:00 Intro\n00:00:30 A - One\n01:01:40 B - Two\n"
+        );
+        assert_eq!(
+            export(&plays[..1], Format::YouTube, 1030),
+            "00:00:00 A - One\n"
+        );
+    }
+
+    #[test]
+    fn exports_cue_sheet_in_minutes() {
+        let plays = [play(1000, "A", "Say \"Hi\""), play(4725, "B", "Two")];
+        assert_eq!(
+            export(&plays, Format::Cue, 1000),
+            "TITLE \"Tracklist\"\n\
+             FILE \"stream.wav\" WAVE\n  \
+             TRACK 01 AUDIO\n    \
+             TITLE \"Say 'Hi'\"\n    \
+             PERFORMER \"A\"\n    \
+             INDEX 01 00:00:00\n  \
+             TRACK 02 AUDIO\n    \
+             TITLE \"Two\"\n    \
+             PERFORMER \"B\"\n    \
+             INDEX 01 62:05:00\n"
+        );
+    }
+
+    #[test]
+    fn exports_m3u8_with_comments_for_unknown_paths() {
+        let mut local = play(1000, "A", "One");
+        local.path = Some("/music/one.flac".to_string());
+        let mut live = play(1200, "B", "Two");
+        live.duration = None;
+        assert_eq!(
+            export(&[local, live], Format::M3u8, 1000),
+            "#EXTM3U\n#EXTINF:200,A - One\n/music/one.flac\n#EXTINF:-1,B - Two\n# B - Two\n"
+        );
+    }
+
+    #[test]
+    fn exports_escaped_xspf() {
+        let mut local = play(1000, "Simon & Garfunkel", "<Mrs. Robinson>");
+        local.album = Some("Bookends".to_string());
+        local.path = Some("/music/mrs robinson.flac".to_string());
+        let xspf = export(&[local], Format::Xspf, 1000);
+        if cfg!(unix) {
+            // Not an absolute path on Windows, so it has no location there
+            assert!(xspf.contains("<location>file:///music/mrs%20robinson.flac</location>"));
+        }
+        assert!(xspf.contains("<creator>Simon &amp; Garfunkel</creator>"));
+        assert!(xspf.contains("<title>&lt;Mrs. Robinson&gt;</title>"));
+        assert!(xspf.contains("<album>Bookends</album>"));
+        assert!(xspf.contains("<duration>200000</duration>"));
+        assert!(xspf.ends_with("  </trackList>\n</playlist>\n"));
+    }
+}
@@ -0,0 +1,505 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Error};
+use flume::{Receiver, Sender, TryRecvError};
+use tungstenite::{Message, WebSocket};
+use url::form_urlencoded;
+
+use crate::{
+    app::LifecycleEvent,
+    config::{ChatPermission, Config, TwitchConfig},
+    driver::PlayerCommand,
+    song::SongInfo,
+    Actor, ActorHandle,
+};
+
+/// How long a read may block before checking for song changes.
+const READ_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How long to wait for the server while connecting and logging in.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delay before reconnecting after the connection is lost.
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+trait ChatStream: Read + Write + Send {}
+impl<T: Read + Write + Send> ChatStream for T {}
+
+/// A chat command the bot answers.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Command {
+    Song,
+    LastSong,
+    SongLink,
+    Skip,
+}
+
+impl Command {
+    fn parse(message: &str) -> Option<Command> {
+        match message
+            .split_whitespace()
+            .next()?
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "!song" => Some(Command::Song),
+            "!lastsong" => Some(Command::LastSong),
+            "!songlink" => Some(Command::SongLink),
+            "!skip" => Some(Command::Skip),
+            _ => None,
+        }
+    }
+}
+
+/// A message sent to the channel by a chatter.
+struct ChatMessage<'a> {
+    user: &'a str,
+    text: &'a str,
+    /// Whether the chatter is the broadcaster or a moderator of the channel.
+    moderator: bool,
+}
+
+/// An IRC line split into its parts: `@tags :prefix COMMAND params`.
+struct IrcLine<'a> {
+    tags: &'a str,
+    /// Who sent the line, `nick!user@host` for chatters and a host name for the server.
+    prefix: &'a str,
+    command: &'a str,
+    params: &'a str,
+}
+
+impl IrcLine<'_> {
+    /// Whether the line comes from the chat server itself rather than a chatter.
+    fn is_from_server(&self) -> bool {
+        !self.prefix.contains('!')
+    }
+}
+
+/// Splits a line received from the server into its parts.
+fn parse_line(line: &str) -> Option<IrcLine<'_>> {
+    let (tags, line) = match line.strip_prefix('@') {
+        Some(line) => line.split_once(' ')?,
+        None => ("", line),
+    };
+    let (prefix, line) = match line.strip_prefix(':') {
+        Some(line) => line.split_once(' ')?,
+        None => ("", line),
+    };
+    let (command, params) = line.split_once(' ').unwrap_or((line, ""));
+    Some(IrcLine {
+        tags,
+        prefix,
+        command,
+        params,
+    })
+}
+
+/// Reads a `PRIVMSG` line, such as `:nick!nick@nick.tmi.twitch.tv PRIVMSG #channel :!song`,
+/// optionally preceded by tags such as `@badges=broadcaster/1;mod=0 `.
+fn parse_privmsg<'a>(line: &IrcLine<'a>) -> Option<ChatMessage<'a>> {
+    if line.command != "PRIVMSG" || line.is_from_server() {
+        return None;
+    }
+    let moderator = line.tags.split(';').any(|tag| {
+        tag == "mod=1"
+            || tag
+                .strip_prefix("badges=")
+                .is_some_and(|badges| badges.split(',').any(|b| b.starts_with("broadcaster/")))
+    });
+    let (_channel, text) = line.params.split_once(" :")?;
+    let user = line.prefix.split('!').next()?;
+    Some(ChatMessage {
+        user,
+        text,
+        moderator,
+    })
+}
+
+/// A line from the chat server the bot acts on.
+enum Incoming<'a> {
+    Chat(ChatMessage<'a>),
+    Ping(&'a str),
+    Reconnect,
+    LoginFailed,
+}
+
+/// Tells what a line from the chat server asks for, if anything.
+/// Only the server itself can end the connection, whatever chatters type.
+fn parse_incoming(line: &str) -> Option<Incoming<'_>> {
+    let line = parse_line(line)?;
+    if let Some(message) = parse_privmsg(&line) {
+        return Some(Incoming::Chat(message));
+    }
+    if !line.is_from_server() {
+        return None;
+    }
+    match line.command {
+        "PING" => Some(Incoming::Ping(line.params)),
+        "RECONNECT" => Some(Incoming::Reconnect),
+        "NOTICE" if line.params.ends_with(":Login authentication failed") => {
+            Some(Incoming::LoginFailed)
+        }
+        _ => None,
+    }
+}
+
+/// A connection to the chat server, sending IRC lines as they are or in WebSocket messages.
+enum Connection {
+    Irc {
+        reader: BufReader<Box<dyn ChatStream>>,
+        /// Text of a line that has not been read fully yet.
+        line: String,
+    },
+    WebSocket {
+        socket: Box<WebSocket<Box<dyn ChatStream>>>,
+        /// Lines received but not read yet, as a message may hold several.
+        lines: VecDeque<String>,
+    },
+}
+
+impl Connection {
+    fn open(config: &TwitchConfig) -> Result<Connection, Error> {
+        let address = (config.host.as_str(), config.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("cannot resolve {}", config.host))?;
+        let tcp = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+        // Handshakes fail if a read times out, so they get more time than later reads
+        tcp.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+        tcp.set_write_timeout(Some(CONNECT_TIMEOUT))?;
+        let socket = tcp.try_clone()?;
+        let stream: Box<dyn ChatStream> = if config.tls {
+            let connector = native_tls::TlsConnector::new()?;
+            Box::new(connector.connect(&config.host, tcp)?)
+        } else {
+            Box::new(tcp)
+        };
+
+        let mut connection = if config.websocket {
+            let scheme = if config.tls { "wss" } else { "ws" };
+            let url = format!("{scheme}://{}:{}/", config.host, config.port);
+            let (socket, _) = tungstenite::client(url.as_str(), stream)
+                .map_err(|err| anyhow!("WebSocket handshake failed: {err}"))?;
+            Connection::WebSocket {
+                socket: Box::new(socket),
+                lines: VecDeque::new(),
+            }
+        } else {
+            Connection::Irc {
+                reader: BufReader::new(stream),
+                line: String::new(),
+            }
+        };
+        socket.set_read_timeout(Some(READ_TIMEOUT))?;
+        let token = config.oauth_token.trim_start_matches("oauth:");
+        // Tags tell who the moderators are
+        connection.send("CAP REQ :twitch.tv/tags")?;
+        connection.send(&format!("PASS oauth:{token}"))?;
+        connection.send(&format!("NICK {}", config.username.to_ascii_lowercase()))?;
+        connection.send(&format!("JOIN #{}", channel(config)))?;
+        Ok(connection)
+    }
+
+    fn send(&mut self, line: &str) -> Result<(), Error> {
+        match self {
+            Connection::Irc { reader, .. } => {
+                let stream = reader.get_mut();
+                stream.write_all(line.as_bytes())?;
+                stream.write_all(b"\r\n")?;
+                stream.flush()?;
+            }
+            Connection::WebSocket { socket, .. } => socket.send(Message::Text(line.into()))?,
+        }
+        Ok(())
+    }
+
+    fn say(&mut self, config: &TwitchConfig, text: &str) -> Result<(), Error> {
+        // A line break would end the message and start a new IRC command
+        let text = text.replace(['\r', '\n'], " ");
+        self.send(&format!("PRIVMSG #{} :{text}", channel(config)))
+    }
+
+    /// Reads the next line, or returns `None` if none arrived in time.
+    fn read_line(&mut self) -> Result<Option<String>, Error> {
+        let timed_out = |err: &std::io::Error| {
+            matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+        };
+        match self {
+            Connection::Irc { reader, line } => match reader.read_line(line) {
+                Ok(0) => Err(anyhow!("connection closed")),
+                Ok(_) => {
+                    let complete = line.trim_end().to_string();
+                    line.clear();
+                    Ok(Some(complete))
+                }
+                Err(err) if timed_out(&err) => Ok(None),
+                Err(err) => Err(err.into()),
+            },
+            Connection::WebSocket { socket, lines } => {
+                if let Some(line) = lines.pop_front() {
+                    return Ok(Some(line));
+                }
+                match socket.read() {
+                    Ok(Message::Text(text)) => {
+                        lines.extend(text.lines().filter(|l| !l.is_empty()).map(str::to_string));
+                        Ok(lines.pop_front())
+                    }
+                    Ok(Message::Close(_)) => Err(anyhow!("connection closed")),
+                    Ok(_) => Ok(None),
+                    Err(tungstenite::Error::Io(err)) if timed_out(&err) => Ok(None),
+                    Err(err) => Err(err.into()),
+                }
+            }
+        }
+    }
+}
+
+fn channel(config: &TwitchConfig) -> String {
+    config.channel.trim_start_matches('#').to_ascii_lowercase()
+}
+
+/// An actor that joins a Twitch channel's chat and answers `!song`, `!lastsong` and `!songlink`,
+/// and lets chatters skip the song with `!skip`.
+pub struct TwitchActor {
+    config: Arc<Config>,
+    lifecycle_sender: Sender<LifecycleEvent>,
+    current_song: Option<SongInfo>,
+    last_song: Option<SongInfo>,
+    /// When each command was last answered.
+    command_times: HashMap<Command, Instant>,
+    /// When each chatter last got an answer.
+    user_times: HashMap<String, Instant>,
+}
+
+impl TwitchActor {
+    pub fn new(lifecycle_sender: Sender<LifecycleEvent>, config: Arc<Config>) -> Self {
+        Self {
+            config,
+            lifecycle_sender,
+            current_song: None,
+            last_song: None,
+            command_times: HashMap::new(),
+            user_times: HashMap::new(),
+        }
+    }
+
+    fn twitch_config(&self) -> &TwitchConfig {
+        self.config.twitch()
+    }
+
+    /// Records a song change, returning the announcement to send, if any.
+    /// Custom text shown instead of the song is left out, chatters asking for the song
+    /// are told nothing is playing.
+    fn update(&mut self, song: Option<SongInfo>) -> Option<String> {
+        if let Some(previous) = self.current_song.take() {
+            self.last_song = Some(previous);
+        }
+        self.current_song = song.filter(|song| !song.custom);
+        let template = self.twitch_config().announce.as_deref()?;
+        let song = self.current_song.as_ref()?;
+        Some(render(template, song, ""))
+    }
+
+    /// Builds the answer to a chat message, if it is a command and not on cooldown.
+    fn answer(&mut self, message: &ChatMessage) -> Option<String> {
+        let command = Command::parse(message.text)?;
+        let twitch_config = self.config.twitch();
+        let now = Instant::now();
+        if command == Command::Skip {
+            let allowed = match twitch_config.skip_allowed {
+                ChatPermission::Nobody => false,
+                ChatPermission::Moderators => message.moderator,
+                ChatPermission::Everyone => true,
+            };
+            if !allowed {
+                return None;
+            }
+        }
+
+        let cooldown = Duration::from_secs(twitch_config.cooldown_secs);
+        if let Some(time) = self.command_times.get(&command) {
+            if now.duration_since(*time) < cooldown {
+                return None;
+            }
+        }
+        let user_cooldown = Duration::from_secs(twitch_config.user_cooldown_secs);
+        if let Some(time) = self.user_times.get(message.user) {
+            if now.duration_since(*time) < user_cooldown {
+                return None;
+            }
+        }
+        self.command_times.insert(command, now);
+        self.user_times.insert(message.user.to_string(), now);
+        self.user_times
+            .retain(|_, time| now.duration_since(*time) < user_cooldown);
+
+        let (template, song) = match command {
+            Command::Song => (&twitch_config.song, &self.current_song),
+            Command::LastSong => (&twitch_config.last_song, &self.last_song),
+            Command::SongLink => (&twitch_config.song_link, &self.current_song),
+            Command::Skip => (&twitch_config.skipped, &self.current_song),
+        };
+        if command == Command::Skip && song.is_some() {
+            let _ = self
+                .lifecycle_sender
+                .send(LifecycleEvent::Player(PlayerCommand::Next));
+        }
+        Some(match song {
+            Some(song) => render(template, song, message.user),
+            None => twitch_config
+                .nothing_playing
+                .replace("{user}", message.user),
+        })
+    }
+
+    /// Applies song changes sent to the actor, announcing them in the chat.
+    /// Returns `false` once the app is shutting down.
+    fn receive_songs(
+        &mut self,
+        receiver: &Receiver<Option<SongInfo>>,
+        mut connection: Option<&mut Connection>,
+    ) -> bool {
+        loop {
+            match receiver.try_recv() {
+                Ok(song) => {
+                    let announcement = self.update(song);
+                    if let (Some(text), Some(conn)) = (announcement, connection.as_deref_mut()) {
+                        if let Err(err) = conn.say(self.twitch_config(), &text) {
+                            warn!("  | Cannot announce the song in Twitch chat: {err}");
+                        }
+                    }
+                }
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return false,
+            }
+        }
+    }
+
+    /// Handles chat traffic until the app is shutting down,
+    /// or returns an error if the connection is lost.
+    fn run(
+        &mut self,
+        connection: &mut Connection,
+        receiver: &Receiver<Option<SongInfo>>,
+    ) -> Result<(), Error> {
+        loop {
+            if !self.receive_songs(receiver, Some(connection)) {
+                let _ = connection.send("QUIT");
+                return Ok(());
+            }
+            let Some(line) = connection.read_line()? else {
+                continue;
+            };
+            match parse_incoming(&line) {
+                Some(Incoming::Chat(message)) => {
+                    if let Some(answer) = self.answer(&message) {
+                        connection.say(self.twitch_config(), &answer)?;
+                    }
+                }
+                Some(Incoming::Ping(payload)) => connection.send(&format!("PONG {payload}"))?,
+                Some(Incoming::Reconnect) => return Err(anyhow!("server asked to reconnect")),
+                Some(Incoming::LoginFailed) => {
+                    return Err(anyhow!("login failed, check username and oauth_token"));
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+/// Fills a template's song placeholders, `{user}` and `{query}`,
+/// which is the artist and title encoded for use in a URL.
+fn render(template: &str, song: &SongInfo, user: &str) -> String {
+    let query =
+        form_urlencoded::byte_serialize(format!("{} {}", song.artist, song.title).as_bytes())
+            .collect::<String>();
+    song.format(template)
+        .replace("{user}", user)
+        .replace("{query}", &query)
+}
+
+impl Actor for TwitchActor {
+    type MessageType = Option<SongInfo>;
+    fn spawn(mut self) -> ActorHandle<Self::MessageType> {
+        let (sender, receiver) = flume::unbounded::<Option<SongInfo>>();
+        ActorHandle {
+            sender,
+            thread_handle: thread::spawn(move || loop {
+                let config = self.config.clone();
+                let result = Connection::open(config.twitch())
+                    .and_then(|mut connection| self.run(&mut connection, &receiver));
+                match result {
+                    Ok(()) => break,
+                    Err(err) => warn!("  | Twitch chat disconnected: {err}"),
+                }
+
+                // Keep track of songs while waiting to reconnect
+                let retry_at = Instant::now() + RECONNECT_DELAY;
+                while Instant::now() < retry_at {
+                    if !self.receive_songs(&receiver, None) {
+                        return;
+                    }
+                    thread::sleep(READ_TIMEOUT);
+                }
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_chat_messages_with_tags() {
+        let line = "@badges=broadcaster/1;mod=0 :nick!nick@nick.tmi.twitch.tv PRIVMSG #chan :!song";
+        let Some(Incoming::Chat(message)) = parse_incoming(line) else {
+            panic!("not a chat message");
+        };
+        assert_eq!(message.user, "nick");
+        assert_eq!(message.text, "!song");
+        assert!(message.moderator);
+    }
+
+    #[test]
+    fn acts_on_server_lines() {
+        assert!(matches!(
+            parse_incoming("PING :tmi.twitch.tv"),
+            Some(Incoming::Ping(":tmi.twitch.tv"))
+        ));
+        assert!(matches!(
+            parse_incoming(":tmi.twitch.tv RECONNECT"),
+            Some(Incoming::Reconnect)
+        ));
+        assert!(matches!(
+            parse_incoming(":tmi.twitch.tv NOTICE * :Login authentication failed"),
+            Some(Incoming::LoginFailed)
+        ));
+        assert!(parse_incoming(":tmi.twitch.tv 001 bot :Welcome, GLHF!").is_none());
+    }
+
+    #[test]
+    fn does_not_let_chatters_end_the_connection() {
+        for text in [
+            " RECONNECT",
+            ":tmi.twitch.tv RECONNECT",
+            " NOTICE * :Login authentication failed",
+            "PING :x",
+        ] {
+            let line = format!(":troll!troll@troll.tmi.twitch.tv PRIVMSG #chan :{text}");
+            assert!(
+                matches!(parse_incoming(&line), Some(Incoming::Chat(message)) if message.text == text),
+                "{line}"
+            );
+        }
+        let notice = ":troll!troll@troll.tmi.twitch.tv NOTICE * :Login authentication failed";
+        assert!(parse_incoming(notice).is_none());
+    }
+}
@@ -0,0 +1,174 @@
+use std::{sync::Arc, thread, time::Duration};
+
+use flume::Receiver;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{
+    config::{Config, WebhookConfig},
+    http,
+    json::{NowPlaying, PlaybackState},
+    song::SongInfo,
+    Actor, ActorHandle,
+};
+
+/// Delay before the first retry, doubled after every failed attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest delay between two attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// A request waiting to be sent.
+struct Delivery {
+    body: String,
+    /// Unix time of the song change, sent along to tell retries apart from new changes.
+    timestamp: u64,
+}
+
+/// An actor that POSTs the song to a URL, as configured by one of the `webhooks` entries.
+/// Requests are sent in order on a background thread, so a slow or failing endpoint
+/// does not hold up other outputs.
+pub struct WebhookActor {
+    config: Arc<Config>,
+    index: usize,
+}
+
+impl WebhookActor {
+    /// Creates an actor for the webhook with the provided index in the config.
+    pub fn new(index: usize, config: Arc<Config>) -> Self {
+        Self { config, index }
+    }
+
+    fn webhook_config(&self) -> &WebhookConfig {
+        &self.config.webhooks()[self.index]
+    }
+
+    /// Prepares the request for a song change.
+    fn delivery(&self, song: Option<&SongInfo>) -> Delivery {
+        let now_playing = NowPlaying::new(song);
+        Delivery {
+            body: self.render(&now_playing),
+            timestamp: now_playing.timestamp,
+        }
+    }
+
+    /// Renders the request body, using the `body` template if one is set.
+    fn render(&self, now_playing: &NowPlaying) -> String {
+        let json = serde_json::to_string(now_playing).unwrap_or_default();
+        let Some(template) = &self.webhook_config().body else {
+            return json;
+        };
+
+        // Values are escaped, so they can be put inside JSON strings in the template
+        let escape = |value: &str| {
+            let quoted = serde_json::to_string(value).unwrap_or_default();
+            quoted[1..quoted.len() - 1].to_string()
+        };
+        let empty = SongInfo::default();
+        let song = now_playing.song.unwrap_or(&empty);
+        let state = match now_playing.state {
+            PlaybackState::Playing => "playing",
+            PlaybackState::Stopped => "stopped",
+        };
+        template
+            .replace("{artist}", &escape(&song.artist))
+            .replace("{title}", &escape(&song.title))
+            .replace(
+                "{album}",
+                &escape(song.album.as_deref().unwrap_or_default()),
+            )
+            .replace("{text}", &escape(&self.song_text(song)))
+            .replace("{state}", state)
+            .replace("{timestamp}", &now_playing.timestamp.to_string())
+            .replace("{json}", &json)
+    }
+
+    /// The song formatted with `song_format`, or empty when nothing is playing.
+    fn song_text(&self, song: &SongInfo) -> String {
+        if song.title.is_empty() {
+            String::new()
+        } else {
+            song.format(self.config.song_format())
+        }
+    }
+
+    /// Sends deliveries one by one, retrying each with exponential backoff.
+    fn deliver(&self, deliveries: Receiver<Delivery>) {
+        let webhook_config = self.webhook_config();
+        let agent = http::agent(Duration::from_secs(webhook_config.timeout_secs));
+
+        for delivery in deliveries.iter() {
+            let mut backoff = INITIAL_BACKOFF;
+            let mut attempt = 0;
+            loop {
+                let mut request = agent
+                    .post(&webhook_config.url)
+                    .set("Content-Type", "application/json")
+                    .set("X-CurrentSong-Timestamp", &delivery.timestamp.to_string());
+                for (name, value) in &webhook_config.headers {
+                    request = request.set(name, value);
+                }
+                if let Some(secret) = &webhook_config.secret {
+                    let signature = sign(secret, delivery.timestamp, &delivery.body);
+                    request = request.set(&webhook_config.signature_header, &signature);
+                }
+
+                let retry = match request.send_string(&delivery.body) {
+                    Ok(_) => break,
+                    Err(ureq::Error::Status(status, _)) => {
+                        warn!("  | Webhook {} returned {status}", &webhook_config.url);
+                        status == 408 || status == 429 || status >= 500
+                    }
+                    Err(err) => {
+                        warn!("  | Cannot reach webhook {}: {err}", &webhook_config.url);
+                        true
+                    }
+                };
+                if !retry || attempt >= webhook_config.max_retries {
+                    warn!(
+                        "  | Giving up on a webhook request to {}",
+                        &webhook_config.url
+                    );
+                    break;
+                }
+                attempt += 1;
+                thread::sleep(backoff);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+/// Signs a request as `sha256=<hex>`, an HMAC-SHA256 of `<timestamp>.<body>`.
+fn sign(secret: &str, timestamp: u64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{timestamp}.{body}").as_bytes());
+    let hex = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    format!("sha256={hex}")
+}
+
+impl Actor for WebhookActor {
+    type MessageType = Option<SongInfo>;
+    fn spawn(self) -> ActorHandle<Self::MessageType> {
+        let (sender, receiver) = flume::unbounded::<Option<SongInfo>>();
+        ActorHandle {
+            sender,
+            thread_handle: thread::spawn(move || {
+                let this = Arc::new(self);
+                let (delivery_sender, delivery_receiver) = flume::unbounded();
+                let worker = this.clone();
+                thread::spawn(move || worker.deliver(delivery_receiver));
+
+                while let Ok(song) = receiver.recv() {
+                    let _ = delivery_sender.send(this.delivery(song.as_ref()));
+                }
+            }),
+        }
+    }
+}
@@ -8,11 +8,13 @@ use std::{
 
 use flume::{Receiver, Sender};
 use nwg::{
-    EmbedResource, Event, Font, GridLayout, Icon, Label, NativeUi, Notice, NwgError, Window,
-    WindowFlags,
+    Button, EmbedResource, Event, Font, GridLayout, GridLayoutItem, Icon, Label, NativeUi, Notice,
+    NwgError, Window, WindowFlags,
 };
 
-use crate::{app::LifecycleEvent, config::Config, song::SongInfo, Actor, ActorHandle};
+use crate::{
+    app::LifecycleEvent, config::Config, driver::PlayerCommand, song::SongInfo, Actor, ActorHandle,
+};
 
 pub struct WindowActor {
     sender: Sender<LifecycleEvent>,
@@ -54,6 +56,9 @@ struct WindowApp {
     layout: GridLayout,
     label_artist: Label,
     label_title: Label,
+    button_previous: Button,
+    button_toggle: Button,
+    button_next: Button,
     song_notice: Notice,
     current_song: Arc<Mutex<Option<SongInfo>>>,
     sender: Option<Sender<LifecycleEvent>>,
@@ -96,6 +101,12 @@ impl WindowApp {
         }
     }
 
+    fn on_player_button(&self, command: PlayerCommand) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(LifecycleEvent::Player(command));
+        }
+    }
+
     fn on_window_close(&self) {
         nwg::stop_thread_dispatch();
         if let Some(sender) = &self.sender {
@@ -115,7 +126,7 @@ impl NativeUi<WindowUi> for WindowApp {
     fn build_ui(mut state: Self) -> Result<WindowUi, NwgError> {
         let embed = EmbedResource::load(None)?;
         Window::builder()
-            .size((400, 120))
+            .size((400, 160))
             .flags(WindowFlags::union(
                 WindowFlags::union(WindowFlags::WINDOW, WindowFlags::VISIBLE),
                 WindowFlags::MINIMIZE_BOX,
@@ -138,13 +149,32 @@ impl NativeUi<WindowUi> for WindowApp {
             .parent(&state.window)
             .build(&mut state.label_title)?;
 
+        Button::builder()
+            .text("⏮")
+            .parent(&state.window)
+            .build(&mut state.button_previous)?;
+
+        Button::builder()
+            .text("⏯")
+            .parent(&state.window)
+            .build(&mut state.button_toggle)?;
+
+        Button::builder()
+            .text("⏭")
+            .parent(&state.window)
+            .build(&mut state.button_next)?;
+
         GridLayout::builder()
             .parent(&mut state.window)
-            .max_row(Some(2))
+            .max_row(Some(3))
+            .max_column(Some(3))
             .spacing(5)
             .margin([30, 15, 30, 15])
-            .child(0, 0, &state.label_artist)
-            .child(0, 1, &state.label_title)
+            .child_item(GridLayoutItem::new(&state.label_artist, 0, 0, 3, 1))
+            .child_item(GridLayoutItem::new(&state.label_title, 0, 1, 3, 1))
+            .child(0, 2, &state.button_previous)
+            .child(1, 2, &state.button_toggle)
+            .child(2, 2, &state.button_next)
             .build(&state.layout)?;
 
         let ui = WindowUi {
@@ -158,6 +188,15 @@ impl NativeUi<WindowUi> for WindowApp {
                 match evt {
                     Event::OnInit => app.on_init(),
                     Event::OnNotice => app.on_notice(),
+                    Event::OnButtonClick => {
+                        if handle == app.button_previous {
+                            app.on_player_button(PlayerCommand::Previous);
+                        } else if handle == app.button_toggle {
+                            app.on_player_button(PlayerCommand::Toggle);
+                        } else if handle == app.button_next {
+                            app.on_player_button(PlayerCommand::Next);
+                        }
+                    }
                     Event::OnWindowClose => {
                         if &handle == &app.window {
                             app.on_window_close();